@@ -0,0 +1,304 @@
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+
+use crate::constants::CODATA_2022;
+use crate::defs::{self, PREFIXES};
+use crate::parse::TO_KEYWORDS;
+use crate::registry::{BUILTIN, Registry, Source, Unit};
+
+// SI values of well-known units, from their legal definitions (NIST SP 811,
+// IAU 2012 B2, CODATA 2022), kept apart from units.def to catch typos there
+const REFERENCE: &[(&str, f64)] = &[
+    ("in", 0.0254),
+    ("ft", 0.3048),
+    ("yd", 0.9144),
+    ("mi", 1609.344),
+    ("angstrom", 1e-10),
+    ("AU", 149_597_870_700.0),
+    ("ly", 9_460_730_472_580_800.0),
+    ("pc", 3.085_677_581_491_367e16),
+    ("minute", 60.0),
+    ("hour", 3600.0),
+    ("day", 86_400.0),
+    ("week", 604_800.0),
+    ("year", 31_556_952.0),
+    ("julian_year", 31_557_600.0),
+    ("lb", 0.453_592_37),
+    ("oz", 0.028_349_523_125),
+    ("t", 1000.0),
+    ("amu", 1.660_539_068_92e-27),
+    ("cal", 4.184),
+    ("kcal", 4184.0),
+    ("kWh", 3.6e6),
+    ("erg", 1e-7),
+    ("eV", 1.602_176_634e-19),
+    ("dyn", 1e-5),
+    ("bar", 1e5),
+    ("atm", 101_325.0),
+    ("Torr", 133.322_368_421_052_63),
+    ("mmHg", 133.322_387_415),
+    ("psi", 6_894.757_293_168_361),
+    ("L", 1e-3),
+    ("gal", 3.785_411_784e-3),
+    ("qt", 9.463_529_46e-4),
+    ("pt", 4.731_764_73e-4),
+    ("cup", 2.365_882_365e-4),
+    ("floz", 2.957_352_956_25e-5),
+    ("tsp", 4.928_921_593_75e-6),
+    ("gal_imp", 4.546_09e-3),
+    ("statC", 3.335_640_951_981_52e-10),
+    ("gauss", 1e-4),
+    ("Mx", 1e-8),
+    ("Oe", 79.577_471_545_947_67),
+    ("P", 0.1),
+    ("St", 1e-4),
+    ("fc", 10.763_910_416_709_722),
+    ("Ci", 3.7e10),
+    ("R", 2.58e-4),
+    ("rad_dose", 1e-2),
+    ("rem", 1e-2),
+];
+
+/// A problem found in the unit catalog.
+#[derive(Debug, PartialEq)]
+pub enum Issue {
+    /// a symbol or name given to more than one unit, with the lines
+    Duplicate(String, Vec<usize>),
+    /// a symbol or name that also reads as a prefix and another unit, with
+    /// a different value
+    Shadows(String, String),
+    /// a symbol or name that the parser takes for a keyword
+    Keyword(String, &'static str),
+    /// a factor that disagrees with the reference value
+    Mismatch(String, f64, f64),
+}
+
+impl Issue {
+    /// Shadowing is often deliberate (`min`, `ft`), the rest are errors.
+    pub fn is_error(&self) -> bool {
+        !matches!(self, Issue::Shadows(..))
+    }
+}
+
+impl Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Issue::Duplicate(name, lines) => {
+                let lines: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+                write!(f, "{name} is defined on lines {}", lines.join(", "))
+            }
+            Issue::Shadows(name, reading) => {
+                write!(f, "{name} also reads as {reading}")
+            }
+            Issue::Keyword(name, keyword) => {
+                write!(f, "{name} collides with the keyword `{keyword}`")
+            }
+            Issue::Mismatch(symbol, factor, expected) => {
+                write!(
+                    f,
+                    "{symbol} is {factor:e} in SI units, expected {expected:e}"
+                )
+            }
+        }
+    }
+}
+
+/// Checks the built-in catalog as written, and the units of `registry`.
+pub fn check(registry: &Registry) -> Vec<Issue> {
+    let mut issues = duplicates(BUILTIN);
+    issues.extend(shadows(registry));
+    issues.extend(keywords(registry));
+    issues.extend(mismatches(registry));
+    issues
+}
+
+// The registry stops at the first duplicate it meets; this lists them all,
+// prefixed forms and the `[names]` section included.
+fn duplicates(text: &str) -> Vec<Issue> {
+    let Ok(defs) = defs::parse(text) else {
+        return Vec::new();
+    };
+    let mut seen: HashMap<String, Vec<(usize, String)>> = HashMap::new();
+    let mut add = |name: String, line: usize, symbol: &str| {
+        let entries = seen.entry(name).or_default();
+        // `bar` may list `bar` among its names
+        if !entries.iter().any(|(l, s)| *l == line && s == symbol) {
+            entries.push((line, symbol.to_string()));
+        }
+    };
+    for decl in &defs.units {
+        add(decl.symbol.clone(), decl.line, &decl.symbol);
+        for name in &decl.names {
+            add(name.clone(), decl.line, &decl.symbol);
+        }
+        for prefix in &decl.prefixes {
+            let symbol = format!("{}{}", prefix.symbol, decl.symbol);
+            add(symbol.clone(), decl.line, &symbol);
+            for name in decl.names.iter().filter(|name| name.is_ascii()) {
+                add(format!("{}{name}", prefix.name), decl.line, &symbol);
+            }
+        }
+    }
+    for (line, symbol, names) in &defs.names {
+        for name in names {
+            add(name.clone(), *line, symbol);
+        }
+    }
+    let mut issues: Vec<Issue> = seen
+        .into_iter()
+        .filter(|(_, entries)| entries.len() > 1)
+        .map(|(name, entries)| Issue::Duplicate(name, entries.iter().map(|&(l, _)| l).collect()))
+        .collect();
+    issues.sort_by_key(|issue| issue.to_string());
+    issues
+}
+
+fn same_unit(a: &Unit, factor: f64, b: &Unit) -> bool {
+    let a_si = a.def.si_factor(&CODATA_2022);
+    let b_si = factor * b.def.si_factor(&CODATA_2022);
+    a.def.dim == b.def.dim && ((a_si - b_si) / b_si).abs() < 1e-9
+}
+
+// `min` also reads as milli-inch; harmless as long as it means the same,
+// like `kg` or `km`
+fn shadows(registry: &Registry) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let mut check = |name: &str, unit: &Unit, symbols: bool| {
+        for prefix in PREFIXES {
+            let head = if symbols { prefix.symbol } else { prefix.name };
+            if let Some(rest) = name.strip_prefix(head)
+                && !rest.is_empty()
+                && let Some(base) = registry.lookup(rest)
+                && !same_unit(unit, prefix.factor, base)
+            {
+                issues.push(Issue::Shadows(
+                    name.to_string(),
+                    format!("{}-{}", prefix.name, base.symbol),
+                ));
+            }
+        }
+    };
+    for group in registry.groups() {
+        for symbol in &group.units {
+            check(symbol, registry.unit(symbol).unwrap(), true);
+        }
+    }
+    for (name, symbol) in registry.names() {
+        check(name, registry.unit(symbol).unwrap(), false);
+    }
+    issues.sort_by_key(|issue| issue.to_string());
+    issues
+}
+
+fn keywords(registry: &Registry) -> Vec<Issue> {
+    // `to`, `of <substance>`, the atomic unit `au` and the targets that
+    // are not units
+    let mut keywords: Vec<&'static str> = TO_KEYWORDS.to_vec();
+    keywords.extend([
+        "of", "au", "auto", "simplest", "si", "natural", "planck", "gaussian", "duration",
+        "iso8601",
+    ]);
+    let all = registry
+        .groups()
+        .iter()
+        .flat_map(|group| group.units.iter().map(String::as_str))
+        .chain(registry.names().map(|(name, _)| name));
+    let mut issues = Vec::new();
+    for name in all {
+        // longer names are also found ignoring case, but the parser takes
+        // a bare `au` before looking up units, which leaves `AU` alone
+        let folded = name.chars().count() > 1;
+        if let Some(keyword) = keywords
+            .iter()
+            .find(|&&kw| kw == name || (folded && kw != "au" && kw.eq_ignore_ascii_case(name)))
+        {
+            issues.push(Issue::Keyword(name.to_string(), keyword));
+        }
+    }
+    issues.sort_by_key(|issue| issue.to_string());
+    issues
+}
+
+// only built-in units, `def!` may replace them on purpose
+fn mismatches(registry: &Registry) -> Vec<Issue> {
+    REFERENCE
+        .iter()
+        .filter_map(|&(symbol, expected)| {
+            let unit = registry.unit(symbol)?;
+            let factor = unit.def.si_factor(&CODATA_2022);
+            (unit.source == Source::Builtin && ((factor - expected) / expected).abs() > 1e-12)
+                .then(|| Issue::Mismatch(symbol.to_string(), factor, expected))
+        })
+        .collect()
+}
+
+/// `--check-db`: prints the issues of the session's catalog. Returns
+/// whether it is free of errors.
+pub fn run() -> bool {
+    let issues = crate::registry::with(check);
+    let errors = issues.iter().filter(|issue| issue.is_error()).count();
+    for issue in &issues {
+        let level = if issue.is_error() { "error" } else { "warning" };
+        println!("{level}: {issue}");
+    }
+    let (groups, units) = crate::registry::with(|r| {
+        (
+            r.groups().len(),
+            r.groups().iter().map(|g| g.units.len()).sum::<usize>(),
+        )
+    });
+    println!(
+        "{units} units in {groups} groups, {errors} errors, {} warnings",
+        issues.len() - errors
+    );
+    errors == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_catalog_is_consistent() {
+        let issues = check(&Registry::builtin());
+        let errors: Vec<String> = issues
+            .iter()
+            .filter(|issue| issue.is_error())
+            .map(|issue| issue.to_string())
+            .collect();
+        assert!(errors.is_empty(), "{errors:#?}");
+        // the symbol always wins, adding to this list should be a decision
+        let shadowing: Vec<&str> = issues
+            .iter()
+            .filter_map(|issue| match issue {
+                Issue::Shadows(name, _) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            shadowing,
+            ["ang", "cfm", "fL", "ft", "microns", "min", "ppm", "ppt", "pt", "qt"]
+        );
+    }
+
+    #[test]
+    fn finds_catalog_errors() {
+        let text = "[length: m]\nm = !length\n  prefixes: k\nkm = 1000 m\nft = 0.3048 m\n\
+            [time: s]\ns = !time\nminute = 60 s\n  names: ft\n";
+        assert_eq!(
+            duplicates(text),
+            [
+                Issue::Duplicate("ft".into(), vec![5, 8]),
+                Issue::Duplicate("km".into(), vec![2, 4]),
+            ]
+        );
+        let mut registry = Registry::builtin();
+        registry.load("[user]\nto_ = 1 m\nOf = 2 m\n", Source::User).unwrap();
+        assert_eq!(keywords(&registry), [Issue::Keyword("Of".into(), "of")]);
+        registry.load("[user]\nmcm = 5 cm\n", Source::User).unwrap();
+        let shadows = shadows(&registry);
+        assert!(shadows.iter().any(|issue| matches!(issue, Issue::Shadows(n, _) if n == "mcm")));
+        registry.load("[typo]\nft = 0.348 m\n  force: yes\n", Source::Builtin).unwrap();
+        assert_eq!(mismatches(&registry), [Issue::Mismatch("ft".into(), 0.348, 0.3048)]);
+    }
+}
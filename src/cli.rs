@@ -1,5 +1,12 @@
+use std::path::PathBuf;
+
 use crate::convert::convert;
+use crate::currency::{self, RatesError};
+use crate::explain::explain;
+use crate::import::{self, ImportError};
 use crate::parse::parse_expr;
+use crate::render::{render, render_json};
+use crate::settings::{self, SettingsError};
 use clap::Parser;
 #[derive(Debug, Parser)]
 #[command(
@@ -9,12 +16,125 @@ use clap::Parser;
 )]
 pub struct Cli {
     pub expr: Option<String>,
+    /// CODATA release used for eV, amu and atomic units
+    #[arg(long, value_name = "YEAR")]
+    pub codata: Option<String>,
+    /// unit system deciding which dimensions are interchangeable
+    #[arg(long, value_name = "SYSTEM")]
+    pub system: Option<String>,
+    /// reject conversions between different quantity kinds, e.g. torque to energy
+    #[arg(long)]
+    pub strict: bool,
+    /// link radiant and luminous units by a luminous efficacy, 683 lm/W if no value is given
+    #[arg(
+        long,
+        value_name = "LM_PER_W",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "on"
+    )]
+    pub efficacy: Option<String>,
+    /// how results are written: shortest, plain, sig, fixed, sci or eng
+    #[arg(long, value_name = "MODE")]
+    pub notation: Option<String>,
+    /// significant digits, or decimals with --notation fixed
+    #[arg(long, value_name = "N")]
+    pub precision: Option<String>,
+    /// print results as fractions such as 1/3 when every factor is exact,
+    /// whatever the notation
+    #[arg(long)]
+    pub exact: bool,
+    /// preferences for `to auto`, e.g. chemistry for bond lengths in angstrom
+    #[arg(long, value_name = "PROFILE")]
+    pub profile: Option<String>,
+    /// exchange rates file (CSV or JSON), defaults to rates.json or rates.csv in the config dir
+    #[arg(long, value_name = "FILE")]
+    pub rates: Option<PathBuf>,
+    /// adds the units of a GNU units or pint definitions file, may be repeated
+    #[arg(long, value_name = "FILE")]
+    pub import_defs: Vec<PathBuf>,
+    /// check the unit catalog for duplicate, shadowed and mistyped units, then exit
+    #[arg(long)]
+    pub check_db: bool,
+    /// print the result of EXPR as JSON
+    #[arg(long)]
+    pub json: bool,
+}
+
+impl Cli {
+    /// Pushes the flags into the session settings.
+    pub fn apply_settings(&self) -> Result<(), SettingsError> {
+        if let Some(year) = &self.codata {
+            settings::set("codata", year)?;
+        }
+        if let Some(system) = &self.system {
+            settings::set("system", system)?;
+        }
+        if self.strict {
+            settings::set("strict", "on")?;
+        }
+        if let Some(efficacy) = &self.efficacy {
+            settings::set("efficacy", efficacy)?;
+        }
+        if let Some(notation) = &self.notation {
+            settings::set("notation", notation)?;
+        }
+        if let Some(precision) = &self.precision {
+            settings::set("precision", precision)?;
+        }
+        if self.exact {
+            settings::set("exact", "on")?;
+        }
+        if let Some(profile) = &self.profile {
+            settings::set("profile", profile)?;
+        }
+        Ok(())
+    }
+
+    /// Loads the exchange rates given by `--rates`, or the ones in the
+    /// config dir. Only a file given by `--rates` is an error.
+    pub fn load_rates(&self) -> Result<(), RatesError> {
+        match &self.rates {
+            Some(path) => currency::load(path),
+            None => {
+                currency::load_default();
+                Ok(())
+            }
+        }
+    }
+
+    /// Imports the files given by `--import-defs`. Lines that could not be
+    /// imported are reported on stderr.
+    pub fn import_defs(&self) -> Result<(), ImportError> {
+        for path in &self.import_defs {
+            let report = import::import(path)?;
+            for (line, reason) in &report.skipped {
+                eprintln!("{}:{}: {}", path.display(), line, reason);
+            }
+        }
+        Ok(())
+    }
 }
 
-pub fn run_once(line: &str) {
+pub fn run_once(line: &str, json: bool) {
+    if let Some(rest) = line.trim_start().strip_prefix("explain ") {
+        match parse_expr(rest.trim_start()) {
+            Ok(expr) if json => println!("{}", explain(&expr).json()),
+            Ok(expr) => println!("{}", explain(&expr)),
+            Err(e) if json => println!("{}", serde_json::json!({ "error": e.to_string() })),
+            Err(e) => println!("{e}"),
+        }
+        return;
+    }
     match parse_expr(line) {
+        Ok(expr) if json => {
+            println!("{}", render_json(&convert(&expr)));
+        }
         Ok(expr) => {
-            println!("{}", convert(&expr));
+            println!("{}", render(&convert(&expr)));
+        }
+        Err(e) if json => {
+            println!("{}", serde_json::json!({ "error": e.to_string() }));
         }
         Err(e) => {
             println!("{e}");
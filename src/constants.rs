@@ -1,218 +1,89 @@
-use std::{collections::HashMap, sync::LazyLock};
-
-use crate::units::{Dim, UnitDef};
-
-pub const AU_MASS: f64 = 9.1093826e-31;
-pub const AU_LENGTH: f64 = 5.291772108e-11;
-pub const AU_TIME: f64 = 2.418884326505e-17;
-pub const AU_CURRENT: f64 = 6.62361782e-3;
-pub const AU_TEMPERATURE: f64 = 3.1577464e5;
-pub const E: f64 = 1.60217653e-19;
-
-type UnitsType = &'static [(&'static str, f64)];
-
-pub static LENGTH_UNITS: UnitsType = &[
-    ("m", 1.0),
-    ("km", 1e3),
-    ("dm", 1e-1),
-    ("cm", 1e-2),
-    ("mm", 1e-3),
-    ("um", 1e-6),
-    ("nm", 1e-9),
-    ("angstrom", 1e-10),
-    ("ang", 1e-10),
-    ("pm", 1e-12),
-    ("fm", 1e-15),
-];
-
-pub static TIME_UNITS: UnitsType = &[
-    ("day", 86400.0), // 24 * 3600
-    ("hour", 3600.0),
-    ("minute", 60.0),
-    ("min", 60.0),
-    ("s", 1.0),
-    ("ms", 1e-3),
-    ("us", 1e-6),
-    ("ns", 1e-9),
-    ("ps", 1e-12),
-    ("fs", 1e-15),
-];
-
-pub static TEMP_UNITS: UnitsType = &[("K", 1.0)];
-
-pub static CURRENT_UNITS: UnitsType = &[
-    ("A", 1.0),
-    ("kA", 1e3),
-    ("MA", 1e6),
-    ("mA", 1e-3),
-    ("uA", 1e-6),
-    ("nA", 1e-9),
-    ("pA", 1e-12),
-    ("fA", 1e-15),
-];
-
-pub static MASS_UNITS: UnitsType = &[
-    ("kg", 1.0),
-    ("g", 1e-3),
-    ("mg", 1e-6),
-    ("ug", 1e-9),
-    ("ng", 1e-12),
-    ("pg", 1e-15),
-    ("t", 1e3),
-    ("amu", 1.66053904e-27),
-    ("Da", 1.66053904e-27),
-];
-pub static ENERGY_UNITS: UnitsType = &[
-    // SI joule family
-    ("J", 1.0),
-    ("kJ", 1e3),
-    ("MJ", 1e6),
-    ("GJ", 1e9),
-    ("mJ", 1e-3),
-    ("uJ", 1e-6),
-    ("nJ", 1e-9),
-    ("pJ", 1e-12),
-    // electron-volt (exact, since e is exact)
-    ("eV", E),
-    ("keV", 1e3 * E),
-    ("MeV", 1e6 * E),
-    ("GeV", 1e9 * E),
-    ("cal", 4.184),
-    ("kcal", 4184.0),
-    ("Cal", 4184.0), // food Calorie = kilocalorie
-    ("Wh", 3600.0),
-    ("kWh", 3.6e6),
-    ("MWh", 3.6e9),
-];
-
-pub static CHARGE_UNITS: UnitsType = &[
-    ("C", 1.0),
-    ("kC", 1e3),
-    ("mC", 1e-3),
-    ("uC", 1e-6),
-    ("nC", 1e-9),
-    ("pC", 1e-12),
-    ("Ah", 3600.0),
-    ("mAh", 3.6),
-];
-
-pub static FORCE_UNITS: UnitsType = &[
-    ("N", 1.0),
-    ("kN", 1e3),
-    ("MN", 1e6),
-    ("mN", 1e-3),
-    ("uN", 1e-6),
-    ("dyn", 1e-5), // dyne
-];
-
-pub static PRESSURE_UNITS: UnitsType = &[
-    ("Pa", 1.0),
-    ("hPa", 100.0),
-    ("kPa", 1e3),
-    ("MPa", 1e6),
-    ("GPa", 1e9),
-    ("bar", 1e5),
-    ("mbar", 1e2),
-    ("atm", 101_325.0),
-    ("Torr", 133.32236842105263),
-    ("mmHg", 133.322387415),
-    ("psi", 6894.757293168),
-    ("barye", 0.1), // 1 Ba = 0.1 Pa
-];
-
-pub static VOLTAGE_UNITS: UnitsType = &[
-    ("V", 1.0),
-    ("kV", 1e3),
-    ("MV", 1e6),
-    ("GV", 1e9),
-    ("mV", 1e-3),
-    ("uV", 1e-6),
-    ("nV", 1e-9),
-    ("pV", 1e-12),
-];
-
-pub static UNIT_GROUP_MAP: LazyLock<HashMap<&'static str, UnitsType>> = LazyLock::new(|| {
-    HashMap::from([
-        ("length", LENGTH_UNITS),
-        ("time", TIME_UNITS),
-        ("temperature", TEMP_UNITS),
-        ("current", CURRENT_UNITS),
-        ("mass", MASS_UNITS),
-        ("energy", ENERGY_UNITS),
-        ("charge", CHARGE_UNITS),
-        ("force", FORCE_UNITS),
-        ("pressure", PRESSURE_UNITS),
-        ("voltage", VOLTAGE_UNITS),
-    ])
-});
-
-fn to_entry(name: &'static str, dim: Dim, factor_to_si: f64) -> (&'static str, UnitDef) {
-    (
-        name,
-        UnitDef {
-            dim,
-            factor: factor_to_si,
-        },
-    )
+/// speed of light in vacuum, m/s (exact)
+pub const SPEED_OF_LIGHT: f64 = 299_792_458.0;
+
+/// luminous efficacy of 540 THz (555 nm) radiation, lm/W (exact, defines the candela)
+pub const LUMINOUS_EFFICACY_555NM: f64 = 683.0;
+
+/// One CODATA release of the measured constants that derived units depend on.
+#[derive(Debug)]
+pub struct Codata {
+    pub year: u16,
+    /// elementary charge, C
+    pub e: f64,
+    /// atomic mass constant, kg
+    pub amu: f64,
+    /// reduced Planck constant, J*s
+    pub hbar: f64,
+    /// Boltzmann constant, J/K
+    pub k_b: f64,
+    /// Newtonian constant of gravitation, m^3/(kg*s^2)
+    pub g: f64,
+    pub au_mass: f64,
+    pub au_length: f64,
+    pub au_time: f64,
+    pub au_current: f64,
+    pub au_temperature: f64,
 }
 
-pub static UNIT_DEF_MAP: LazyLock<HashMap<&'static str, UnitDef>> = LazyLock::new(|| {
-    let mut map = HashMap::new();
-    map.extend(
-        LENGTH_UNITS
-            .iter()
-            .map(|&(name, f)| to_entry(name, Dim::len(1), f)),
-    );
-
-    map.extend(
-        TIME_UNITS
-            .iter()
-            .map(|&(name, f)| to_entry(name, Dim::time(1), f)),
-    );
-
-    map.extend(
-        TEMP_UNITS
-            .iter()
-            .map(|&(name, f)| to_entry(name, Dim::temp(1), f)),
-    );
-
-    map.extend(
-        CURRENT_UNITS
-            .iter()
-            .map(|&(name, f)| to_entry(name, Dim::curr(1), f)),
-    );
-
-    map.extend(
-        MASS_UNITS
-            .iter()
-            .map(|&(name, f)| to_entry(name, Dim::mass(1), f)),
-    );
-    map.extend(
-        ENERGY_UNITS
-            .iter()
-            .map(|&(name, f)| to_entry(name, Dim::new(2, 1, -2, 0, 0), f)),
-    );
-    map.extend(
-        CHARGE_UNITS
-            .iter()
-            .map(|&(name, f)| to_entry(name, Dim::new(0, 0, 1, 0, 1), f)),
-    );
-
-    map.extend(
-        FORCE_UNITS
-            .iter()
-            .map(|&(name, f)| to_entry(name, Dim::new(1, 1, -2, 0, 0), f)),
-    );
-
-    map.extend(
-        PRESSURE_UNITS
-            .iter()
-            .map(|&(name, f)| to_entry(name, Dim::new(-1, 1, -2, 0, 0), f)),
-    );
-    map.extend(
-        VOLTAGE_UNITS
-            .iter()
-            .map(|&(name, f)| to_entry(name, Dim::new(2, 1, -3, 0, -1), f)),
-    );
-    map
-});
+pub static CODATA_2006: Codata = Codata {
+    year: 2006,
+    e: 1.602176487e-19,
+    amu: 1.660538782e-27,
+    hbar: 1.054571628e-34,
+    k_b: 1.3806504e-23,
+    g: 6.67428e-11,
+    au_mass: 9.10938215e-31,
+    au_length: 5.2917720859e-11,
+    au_time: 2.418884326505e-17,
+    au_current: 6.62361763e-3,
+    au_temperature: 3.1577465e5,
+};
+
+pub static CODATA_2014: Codata = Codata {
+    year: 2014,
+    e: 1.6021766208e-19,
+    amu: 1.660539040e-27,
+    hbar: 1.054571800e-34,
+    k_b: 1.38064852e-23,
+    g: 6.67408e-11,
+    au_mass: 9.10938356e-31,
+    au_length: 5.2917721067e-11,
+    au_time: 2.418884326509e-17,
+    au_current: 6.623618183e-3,
+    au_temperature: 3.1577513e5,
+};
+
+// since the 2019 SI redefinition e is exact
+pub static CODATA_2018: Codata = Codata {
+    year: 2018,
+    e: 1.602176634e-19,
+    amu: 1.66053906660e-27,
+    hbar: 1.0545718176461565e-34,
+    k_b: 1.380649e-23,
+    g: 6.67430e-11,
+    au_mass: 9.1093837015e-31,
+    au_length: 5.29177210903e-11,
+    au_time: 2.4188843265857e-17,
+    au_current: 6.623618237510e-3,
+    au_temperature: 3.1577502480407e5,
+};
+
+pub static CODATA_2022: Codata = Codata {
+    year: 2022,
+    e: 1.602176634e-19,
+    amu: 1.66053906892e-27,
+    hbar: 1.0545718176461565e-34,
+    k_b: 1.380649e-23,
+    g: 6.67430e-11,
+    au_mass: 9.1093837139e-31,
+    au_length: 5.29177210544e-11,
+    au_time: 2.4188843265864e-17,
+    au_current: 6.6236182375082e-3,
+    au_temperature: 3.1577502480398e5,
+};
+
+pub static CODATA_SETS: [&Codata; 4] = [&CODATA_2006, &CODATA_2014, &CODATA_2018, &CODATA_2022];
+
+pub fn codata_by_year(year: u16) -> Option<&'static Codata> {
+    CODATA_SETS.iter().copied().find(|c| c.year == year)
+}
@@ -1,32 +1,196 @@
-use crate::constants::*;
-use crate::parse::{ConversionExpr, UnitTarget};
-use crate::units::Dim;
-
-fn au_to_si(dim: Dim) -> f64 {
-    AU_LENGTH.powi(dim.len as i32)
-        * AU_MASS.powi(dim.mass as i32)
-        * AU_TIME.powi(dim.time as i32)
-        * AU_TEMPERATURE.powi(dim.temp as i32)
-        * AU_CURRENT.powi(dim.curr as i32)
+use crate::constants::Codata;
+use crate::parse::{Atom, ConversionExpr, UnitExpr, UnitTarget};
+use crate::rational::Ratio;
+use crate::registry;
+use crate::settings::settings;
+use crate::systems::UnitSystem;
+use crate::units::{Dim, Exact};
+
+fn au_to_si(dim: Dim, codata: &Codata) -> f64 {
+    codata.au_length.powi(dim.len)
+        * codata.au_mass.powi(dim.mass)
+        * codata.au_time.powi(dim.time)
+        * codata.au_temperature.powi(dim.temp)
+        * codata.au_current.powi(dim.curr)
+}
+
+/// The outcome of a conversion, before it is formatted. `render` turns it
+/// into the text the REPL prints.
+#[derive(Debug, Clone)]
+pub struct ConversionResult {
+    pub value: f64,
+    /// what `value` is expressed in; seconds for a duration
+    pub target: UnitTarget,
+    /// the target as shown, e.g. `km/hour`, or `eV^-1` for `to natural`
+    pub symbol: String,
+    /// dimension of the target
+    pub dim: Dim,
+    /// SI value of one source unit, times the equivalence used if any
+    pub from_factor: f64,
+    /// SI value of one target unit
+    pub to_factor: f64,
+    /// `value` as a fraction, when the number and every factor and zero
+    /// involved are exact
+    pub exact: Option<Ratio>,
+    /// equivalences and data the result depends on
+    pub notes: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+// the unit `to auto` stands for, see `Registry::auto_unit`
+fn auto_target(expr: &ConversionExpr) -> UnitTarget {
+    let UnitTarget::Unit(from) = &expr.from else {
+        unreachable!("rejected by parse_expr")
+    };
+    let settings = settings();
+    let si_value = expr.value * from.factor;
+    registry::with(|registry| {
+        let unit = registry.auto_unit(
+            &from.symbol,
+            from.dim,
+            si_value,
+            settings.profile.as_deref(),
+            settings.codata,
+        )?;
+        Some(UnitTarget::Unit(UnitExpr {
+            symbol: unit.symbol.clone(),
+            dim: unit.def.dim,
+            factor: unit.def.si_factor(settings.codata),
+            system: unit.def.system,
+            kind: unit.def.kind,
+            offset: unit.def.offset,
+            exact: unit.def.exact,
+            atoms: vec![Atom::new(&unit.symbol, &unit.def, settings.codata)],
+        }))
+    })
+    .unwrap_or_else(|| expr.from.clone())
 }
 
-pub fn convert(expr: &ConversionExpr) -> String {
-    let mut buffer = ryu::Buffer::new();
-    let (from_factor, to_factor, symbol) = match (&expr.from, &expr.to) {
-        (UnitTarget::Au, UnitTarget::Au) => unreachable!(),
-        (UnitTarget::Au, UnitTarget::Unit(to)) => (au_to_si(to.dim), to.factor, to.symbol.as_str()),
-        (UnitTarget::Unit(from), UnitTarget::Au) => (from.factor, au_to_si(from.dim), "au"),
+pub fn convert(expr: &ConversionExpr) -> ConversionResult {
+    let settings = settings();
+    let codata = settings.codata;
+    let to = match &expr.to {
+        UnitTarget::Auto => auto_target(expr),
+        to => to.clone(),
+    };
+    let system_factor = |system: UnitSystem, dim: Dim| {
+        system
+            .factor(dim, codata)
+            .expect("dimension checked by parse_expr")
+    };
+    let (from_factor, to_factor, symbol, dim) = match (&expr.from, &to) {
+        (UnitTarget::Unit(from), UnitTarget::Duration(_)) => {
+            (from.factor, 1.0, String::from("s"), from.dim)
+        }
+        (
+            UnitTarget::Au,
+            UnitTarget::Au
+            | UnitTarget::System(_)
+            | UnitTarget::Duration(_)
+            | UnitTarget::Auto
+            | UnitTarget::Simplest,
+        )
+        | (
+            UnitTarget::System(_)
+            | UnitTarget::Duration(_)
+            | UnitTarget::Auto
+            | UnitTarget::Simplest,
+            _,
+        )
+        | (_, UnitTarget::Auto | UnitTarget::Simplest) => {
+            unreachable!()
+        }
+        (UnitTarget::Au, UnitTarget::Unit(to)) => (
+            au_to_si(to.dim, codata),
+            to.factor,
+            to.symbol.clone(),
+            to.dim,
+        ),
+        (UnitTarget::Unit(from), UnitTarget::Au) => (
+            from.factor,
+            au_to_si(from.dim, codata),
+            String::from("au"),
+            from.dim,
+        ),
+        (UnitTarget::Unit(from), UnitTarget::System(system)) => (
+            from.factor * system_factor(*system, from.dim),
+            1.0,
+            system.symbol(from.dim),
+            from.dim,
+        ),
+        (UnitTarget::Unit(from), UnitTarget::Unit(to)) if expr.bridge.is_some() => {
+            let bridge = expr.bridge.as_ref().unwrap();
+            (
+                from.factor * bridge.factor,
+                to.factor,
+                to.symbol.clone(),
+                to.dim,
+            )
+        }
+        (UnitTarget::Unit(from), UnitTarget::Unit(to)) if from.dim != to.dim => {
+            // bridged by the active system, go through its units
+            let system = settings.system;
+            (
+                from.factor * system_factor(system, from.dim),
+                to.factor * system_factor(system, to.dim),
+                to.symbol.clone(),
+                to.dim,
+            )
+        }
         (UnitTarget::Unit(from), UnitTarget::Unit(to)) => {
-            (from.factor, to.factor, to.symbol.as_str())
+            (from.factor, to.factor, to.symbol.clone(), to.dim)
+        }
+    };
+    // temperature scales shift by their zero, expressed in their own unit
+    let zero = |target: &UnitTarget| match target {
+        UnitTarget::Unit(unit) => unit.offset / unit.factor,
+        _ => 0.0,
+    };
+    let exact = match (&expr.from, &to) {
+        (UnitTarget::Unit(from), UnitTarget::Unit(to))
+            if expr.bridge.is_none() && from.dim == to.dim =>
+        {
+            exact_value(expr.exact_value, from.exact, to.exact)
         }
+        _ => None,
     };
-    let value = expr.value * from_factor / to_factor;
-    format!("{} {}", buffer.format(value), symbol)
+    let value = match exact {
+        // rounded once, so that e.g. `1 m/s to km/hour` is 3.6
+        Some(exact) => exact.to_f64(),
+        None => (expr.value + zero(&expr.from)) * from_factor / to_factor - zero(&to),
+    };
+    let mut notes = Vec::new();
+    if let Some(bridge) = &expr.bridge {
+        notes.push(format!("using {}", bridge.equivalence.description));
+    }
+    notes.extend(expr.notes.iter().cloned());
+    ConversionResult {
+        value,
+        target: to,
+        symbol,
+        dim,
+        from_factor,
+        to_factor,
+        exact,
+        notes,
+        warnings: expr.warnings.clone(),
+    }
+}
+
+// `(value * from + from zero - to zero) / to`, as a fraction
+fn exact_value(value: Option<Ratio>, from: Option<Exact>, to: Option<Exact>) -> Option<Ratio> {
+    let (value, from, to) = (value?, from?, to?);
+    value
+        .mul(from.factor)?
+        .add(from.offset)?
+        .sub(to.offset)?
+        .div(to.factor)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::currency::Rates;
     use crate::parse::parse_expr;
 
     fn approx_eq(a: f64, b: f64, rel: f64, abs: f64) -> bool {
@@ -34,24 +198,39 @@ mod tests {
         d <= abs || d <= rel * b.abs().max(1.0)
     }
 
-    fn parse_value_and_unit(out: &str) -> (f64, &str) {
-        let mut it = out.split_whitespace();
-        let v = it.next().unwrap().parse::<f64>().unwrap();
-        let u = it.next().unwrap();
-        (v, u)
-    }
-
     fn assert_conv(line: &str, expected: f64, expected_unit: &str) {
-        let expr = parse_expr(line).unwrap();
-        let out = convert(&expr);
-        let (v, u) = parse_value_and_unit(&out);
-        assert_eq!(u, expected_unit);
+        let result = convert(&parse_expr(line).unwrap());
+        assert_eq!(result.symbol, expected_unit);
         assert!(
-            approx_eq(v, expected, 1e-12, 1e-12),
-            "line={line} out={out} expected={expected} {expected_unit}"
+            approx_eq(result.value, expected, 1e-12, 1e-12),
+            "line={line} value={} expected={expected} {expected_unit}",
+            result.value
         );
     }
 
+    #[test]
+    fn exact_factors() {
+        let exact = |line: &str| convert(&parse_expr(line).unwrap()).exact.map(|r| r.to_string());
+        assert_eq!(exact("1 ft to in").as_deref(), Some("12"));
+        assert_eq!(exact("1 in to ft").as_deref(), Some("1/12"));
+        assert_eq!(exact("1 cal to J").as_deref(), Some("523/125"));
+        assert_eq!(exact("98.6 degF to degC").as_deref(), Some("37"));
+        assert_eq!(exact("1 eV to J"), None);
+        assert_eq!(exact("1 pc to AU"), None);
+        // one rounding at the end instead of one per factor
+        assert_eq!(convert(&parse_expr("1 m/s to km/hour").unwrap()).value, 3.6);
+    }
+
+    #[test]
+    fn simplest_target() {
+        assert_conv("1 kg*m^2/s^2 to simplest", 1.0, "J");
+        assert_conv("3 kJ/s to simplest", 3.0, "kW");
+        assert_conv("36 km/hour to simplest", 10.0, "m/s");
+        assert_conv("1 Bq to simplest", 1.0, "Bq");
+        assert_conv("2 N*m to simplest", 2.0, "N*m");
+        assert_conv("2 (m/s)^2 to J/kg", 2.0, "J/kg");
+    }
+
     #[test]
     fn basic_length() {
         assert_conv("10 km to m", 10_000.0, "m");
@@ -93,10 +272,212 @@ mod tests {
 
     #[test]
     fn au_conversions() {
-        assert_conv("1 au to m", 5.291772108e-11, "m");
-        assert_conv("1 m to au", 1.0 / 5.291772108e-11, "au");
-        assert_conv("1 au to s", 2.418884326505e-17, "s");
-        assert_conv("1 s to au", 1.0 / 2.418884326505e-17, "au");
+        assert_conv("1 au to m", 5.29177210544e-11, "m");
+        assert_conv("1 m to au", 1.0 / 5.29177210544e-11, "au");
+        assert_conv("1 au to s", 2.4188843265864e-17, "s");
+        assert_conv("1 s to au", 1.0 / 2.4188843265864e-17, "au");
+    }
+
+    #[test]
+    fn codata_selection() {
+        // the factors are tiny, so compare the inverse conversions
+        assert_conv("1 J to eV", 1.0 / 1.602176634e-19, "eV");
+        assert_conv("1 kg to Da", 1.0 / 1.66053906892e-27, "Da");
+        crate::settings::set("codata", "2006").unwrap();
+        assert_conv("1 J to eV", 1.0 / 1.602176487e-19, "eV");
+        assert_conv("1 kg to amu", 1.0 / 1.660538782e-27, "amu");
+        assert_conv("1 m to au", 1.0 / 5.2917720859e-11, "au");
+        crate::settings::set("codata", "2018").unwrap();
+        assert_conv("1 m to au", 1.0 / 5.29177210903e-11, "au");
+        assert!(crate::settings::set("codata", "2002").is_err());
+    }
+
+    #[test]
+    fn natural_units() {
+        crate::settings::set("system", "natural").unwrap();
+        assert_conv("1 GeV^-1 to fm", 0.19732698045930252, "fm");
+        assert_conv("1 eV to K", 11604.518121550082, "K");
+        assert_conv("1 m to natural", 1.0 / 0.19732698045930252e-6, "eV^-1");
+        assert_conv("1 GeV to natural", 1e9, "eV");
+    }
+
+    #[test]
+    fn planck_units() {
+        assert_conv("1 kg to planck", 45_946_711.112_415_87, "m_P");
+        crate::settings::set("system", "planck").unwrap();
+        assert_conv("1 kg to J", 89_875_517_873_681_764.0, "J");
+    }
+
+    #[test]
+    fn gaussian_units() {
+        assert_conv("1 J to gaussian", 1e7, "g*cm^2*s^-2");
+        assert_conv("1 statV*statC to erg", 1.0, "erg");
+        assert_conv("1 statC to Fr", 1.0, "Fr");
+        assert_conv("1 C to gaussian", 2_997_924_580.0, "statC");
+        assert_conv("1 A to gaussian", 2_997_924_580.0, "statC*s^-1");
+        assert_conv("1 V to gaussian", 1.0 / 299.792_458, "g*cm^2*statC^-1*s^-2");
+        crate::settings::set("system", "gaussian").unwrap();
+        assert_conv("1 C to statC", 2_997_924_580.0, "statC");
+        assert_conv("1 T to gauss", 1e4, "gauss");
+        assert!(parse_expr("1 T to gaussian").is_err());
+    }
+
+    #[test]
+    fn astronomy() {
+        assert_conv("1 AU to km", 149_597_870.7, "km");
+        assert_conv("1 astro:au to m", 149_597_870_700.0, "m");
+        assert_conv("1 ua to AU", 1.0, "AU");
+        assert_conv("1 pc to ly", 3.261_563_777_167_433_3, "ly");
+        assert_conv("1 Mpc to kpc", 1000.0, "kpc");
+        assert_conv("1 M_sun to M_earth", 332_946.078_328_069_94, "M_earth");
+        assert_conv("1 julian_year to day", 365.25, "day");
+        assert_conv("1 atomic:au to m", 5.29177210544e-11, "m");
+    }
+
+    #[test]
+    fn calendar_durations() {
+        assert_conv("2 fortnight to day", 28.0, "day");
+        assert_conv("1 year to day", 365.2425, "day");
+        assert_conv("12 month to year", 1.0, "year");
+        assert_conv("PT1H30M to min", 90.0, "min");
+        assert_conv("P1W to day", 7.0, "day");
+        let result = convert(&parse_expr("1.5 day to iso8601").unwrap());
+        assert_eq!(result.value, 129_600.0);
+        assert!(matches!(result.target, UnitTarget::Duration(_)));
+    }
+
+    #[test]
+    fn radiation() {
+        assert_conv("1 Ci to MBq", 37_000.0, "MBq");
+        assert_conv("1 rem to mSv", 10.0, "mSv");
+        assert_conv("100 rad_dose to Gy", 1.0, "Gy");
+        assert_conv("1 R to C/kg", 2.58e-4, "C/kg");
+        assert_conv("1 mSv/year to uSv/hour", 0.114_079_458_624_521_17, "uSv/hour");
+    }
+
+    #[test]
+    fn photometry() {
+        assert_conv("1 fc to lx", 10.763_910_416_709_722, "lx");
+        assert_conv("1 phot to klx", 10.0, "klx");
+        assert_conv("1 sb to nit", 1e4, "nit");
+        assert_conv("1 cd*sr to lm", 1.0, "lm");
+        assert_conv("1 W/sr to mW/sr", 1000.0, "mW/sr");
+    }
+
+    #[test]
+    fn luminous_efficacy_is_opt_in() {
+        assert!(parse_expr("1 W to lm").is_err());
+        crate::settings::set("efficacy", "on").unwrap();
+        assert_conv("2 W to lm", 1366.0, "lm");
+        assert_conv("683 lx to W/m^2", 1.0, "W/m^2");
+        let result = convert(&parse_expr("1 W to lm").unwrap());
+        assert_eq!(result.notes, ["using luminous efficacy 683 lm/W"]);
+        assert_eq!(result.from_factor, 683.0);
+        // `cd` only shares a dimension with `lm`
+        assert!(parse_expr("1 W to cd").is_err());
+        assert!(parse_expr("1 cd to W").is_err());
+        assert_conv("683 W/sr to lm/sr", 683.0 * 683.0, "lm/sr");
+    }
+
+    #[test]
+    fn fluid() {
+        assert_conv("1 cP to Pa*s", 1e-3, "Pa*s");
+        assert_conv("1 P to cP", 100.0, "cP");
+        assert_conv("1 cSt to mm^2/s", 1.0, "mm^2/s");
+        assert_conv("1 St to cm^2/s", 1.0, "cm^2/s");
+        assert_conv("1 gpm to L/min", 3.785_411_784, "L/min");
+        assert_conv("1 L/min to mL/s", 1000.0 / 60.0, "mL/s");
+        // 0 degC, 1 atm
+        assert_conv("1 slm to mmol/min", 44.615_033_405_470_32, "mmol/min");
+        assert_conv("1000 sccm to slm", 1.0, "slm");
+    }
+
+    #[test]
+    fn density_bridge() {
+        assert_conv("250 mL of ethanol to g", 197.325, "g");
+        assert_conv("250 mL[ethanol] to g", 197.325, "g");
+        assert_conv("1 kg of water to L", 1.0 / 0.9982, "L");
+        assert_conv("1 L/min of water to g/s", 998.2 / 60.0, "g/s");
+        crate::substances::define_density("syrup", 1330.0);
+        assert_conv("1 L of syrup to kg", 1.33, "kg");
+        let result = convert(&parse_expr("2 cup of flour to g").unwrap());
+        assert_eq!(result.notes, ["using density of flour 528.3 kg/m^3"]);
+    }
+
+    #[test]
+    fn molar_mass_bridge() {
+        assert_conv("10 g H2O to mol", 10.0 / 18.015, "mol");
+        assert_conv("0.5 mol NaCl to g", 29.22, "g");
+        assert_conv("1 mg/dL glucose to mmol/L", 10.0 / 180.156, "mmol/L");
+        assert_conv("1 g of CuSO4·5H2O to mmol", 1000.0 / 249.677, "mmol");
+        assert_conv("1 mol [Ca(OH)2] to g", 74.092, "g");
+        assert_conv("1 L water to mol", 998.2 / 18.015, "mol");
+    }
+
+    #[test]
+    fn currencies() {
+        let rates = Rates::from_csv("date,2026-10-16\nbase,EUR\nUSD,1.25\nJPY,160\n").unwrap();
+        crate::currency::set_rates(Some(rates));
+        assert_conv("1200 USD/month to EUR/year", 1200.0 * 12.0 / 1.25, "EUR/year");
+        assert_conv("1000 JPY to USD", 1000.0 / 160.0 * 1.25, "USD");
+        assert_conv("2 USD/kWh to EUR/MJ", 2.0 / 1.25 / 3.6, "EUR/MJ");
+        assert_conv("10 USD to si", 8.0, "EUR");
+        let result = convert(&parse_expr("1 EUR to USD").unwrap());
+        assert_eq!(result.notes, ["using EUR exchange rates of 2026-10-16"]);
+        assert!(parse_expr("1 USD to m").is_err());
+        // codes are taken before names ignoring case, `AMP` is no `amp`
+        let rates = Rates::from_csv("date,2026-10-16\nbase,EUR\nCUP,25\nAMP,2.5\n").unwrap();
+        crate::currency::set_rates(Some(rates));
+        assert_conv("100 CUP to EUR", 4.0, "EUR");
+        assert_conv("5 AMP to EUR", 2.0, "EUR");
+        assert_conv("1 amp to mA", 1000.0, "mA");
+        crate::currency::set_rates(None);
+        assert!(parse_expr("1 USD to EUR").is_err());
+    }
+
+    #[test]
+    fn dimensionless() {
+        assert_conv("5000 ppm to %", 0.5, "%");
+        assert_conv("3 dozen to 1", 36.0, "1");
+        assert_conv("2 gross to dozen", 24.0, "dozen");
+        assert_conv("25 permille to ppm", 25_000.0, "ppm");
+        assert_conv("1 ppb to ppt", 1000.0, "ppt");
+        assert_conv("120 counts/min to 1/s", 2.0, "1/s");
+        assert_conv("3 counts/s to Hz", 3.0, "Hz");
+        assert_conv("5e6 cells/mL to cells/uL", 5000.0, "cells/uL");
+        assert_conv("1 %*m to mm", 10.0, "mm");
+    }
+
+    #[test]
+    fn names_and_temperatures() {
+        assert_conv("3 kilometres to meters", 3000.0, "meters");
+        assert_conv("90 minutes to hours", 1.5, "hours");
+        assert_conv("2 pounds to kg", 0.907_184_74, "kg");
+        assert_conv("1 mile to feet", 5280.0, "feet");
+        assert_conv("1 KILOMETRE to m", 1000.0, "m");
+        assert_conv("1 kilowatt hour to MJ", 3.6, "MJ");
+        assert_conv("20 degrees Celsius to K", 293.15, "K");
+        assert_conv("100 degC to degF", 212.0, "degF");
+        assert_conv("-40 degF to degC", -40.0, "degC");
+        assert_conv("0 K to degF", -459.67, "degF");
+        assert_conv("491.67 degR to degC", 0.0, "degC");
+        assert_conv("1 J/degC to J/K", 1.0, "J/K");
+        assert_conv("9 degF^1 to degC", -12.777_777_777_777_779, "degC");
+    }
+
+    #[test]
+    fn chinese_units() {
+        assert_conv("10 公里 to 米", 10_000.0, "米");
+        assert_conv("10公里转换为米", 10_000.0, "米");
+        assert_conv("3 斤 换算成 公斤", 1.5, "公斤");
+        assert_conv("1斤换算成两", 10.0, "两");
+        assert_conv("1 亩 to m^2", 666.666_666_666_666_6, "m^2");
+        assert_conv("2 里 转换为 km", 1.0, "km");
+        assert_conv("1 千瓦时 to 焦耳", 3.6e6, "焦耳");
+        assert_conv("1 大气压 to 帕", 101_325.0, "帕");
+        assert_conv("90 分钟 to 小时", 1.5, "小时");
+        assert_conv("36 摄氏度 to degF", 96.8, "degF");
+        assert_conv("1 km转换为m", 1000.0, "m");
     }
 
     #[test]
@@ -105,4 +486,25 @@ mod tests {
         assert_conv("+2.5e2 cm to m", 2.5, "m");
         assert_conv("-1.5e3 m to km", -1.5, "km");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn auto_target() {
+        assert_conv("0.0000123 m to auto", 12.3, "um");
+        assert_conv("123456789 J to auto", 123.456789, "MJ");
+        assert_conv("123456789 J", 123.456789, "MJ");
+        assert_conv("2500 g", 2.5, "kg");
+        assert_conv("1e6 eV", 1.0, "MeV");
+        assert_conv("90 min", 1.5, "hour");
+        assert_conv("1e-7 s", 100.0, "ns");
+        // opted out
+        assert_conv("1013 hPa", 101.3, "kPa");
+        // no group to pick from, or a temperature scale
+        assert_conv("5 m/s", 5.0, "m/s");
+        assert_conv("36 degC", 36.0, "degC");
+        assert_conv("1.54e-10 m", 154.0, "pm");
+        crate::settings::set("profile", "chemistry").unwrap();
+        assert_conv("1.54e-10 m", 1.54, "ang");
+        assert_conv("2e-9 m", 2.0, "nm");
+        assert!(crate::settings::set("profile", "cooking").is_err());
+    }
+}
@@ -0,0 +1,254 @@
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::settings::config_dir;
+use crate::units::{Dim, UnitDef};
+
+#[derive(Debug, Error)]
+pub enum RatesError {
+    #[error("Cannot read {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+
+    #[error("Invalid rates file, line {0}: {1}")]
+    Csv(usize, String),
+
+    #[error("Invalid rates file: {0}")]
+    Json(String),
+}
+
+/// Exchange rates against one base currency, as of one date.
+#[derive(Debug, Clone)]
+pub struct Rates {
+    pub date: String,
+    pub base: String,
+    /// units of each currency per unit of the base, the base itself included
+    pub rates: Vec<(String, f64)>,
+}
+
+thread_local! {
+    static RATES: RefCell<Option<Rates>> = const { RefCell::new(None) };
+    /// why the rates file in the config dir did not load
+    static DEFAULT_ERROR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+fn is_code(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_uppercase())
+}
+
+impl Rates {
+    fn new(
+        date: Option<String>,
+        base: Option<String>,
+        mut rates: Vec<(String, f64)>,
+    ) -> Result<Rates, String> {
+        let date = date.ok_or("missing date")?;
+        let base = base.ok_or("missing base currency")?;
+        if !is_code(&base) {
+            return Err(format!("invalid currency code {base}"));
+        }
+        rates.retain(|(code, _)| *code != base);
+        rates.insert(0, (base.clone(), 1.0));
+        Ok(Rates { date, base, rates })
+    }
+
+    fn check_rate(code: &str, rate: f64) -> Result<(String, f64), String> {
+        if !is_code(code) {
+            return Err(format!("invalid currency code {code}"));
+        }
+        if !(rate.is_finite() && rate > 0.0) {
+            return Err(format!("rate of {code} must be positive"));
+        }
+        Ok((code.to_string(), rate))
+    }
+
+    /// Reads `key,value` lines: `date,<date>`, `base,<code>` and one
+    /// `<code>,<units per base>` line per currency. `#` starts a comment.
+    pub fn from_csv(text: &str) -> Result<Rates, RatesError> {
+        let mut date = None;
+        let mut base = None;
+        let mut rates = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let err = |msg: String| RatesError::Csv(i + 1, msg);
+            let (key, value) = line
+                .split_once(',')
+                .map(|(k, v)| (k.trim(), v.trim()))
+                .ok_or_else(|| err(String::from("expected `<key>,<value>`")))?;
+            match key {
+                "date" => date = Some(value.to_string()),
+                "base" => base = Some(value.to_string()),
+                code => {
+                    let rate = value
+                        .parse::<f64>()
+                        .map_err(|_| err(format!("invalid rate {value}")))?;
+                    rates.push(Rates::check_rate(code, rate).map_err(err)?);
+                }
+            }
+        }
+        let last = text.lines().count();
+        Rates::new(date, base, rates).map_err(|msg| RatesError::Csv(last, msg))
+    }
+
+    /// Reads `{"date": ..., "base": ..., "rates": {"<code>": <units per base>}}`.
+    pub fn from_json(text: &str) -> Result<Rates, RatesError> {
+        let err = |msg: String| RatesError::Json(msg);
+        let root: serde_json::Value = serde_json::from_str(text).map_err(|e| err(e.to_string()))?;
+        let field = |key: &str| root.get(key).and_then(|v| v.as_str()).map(String::from);
+        let table = root
+            .get("rates")
+            .and_then(|v| v.as_object())
+            .ok_or_else(|| err(String::from("missing rates object")))?;
+        let mut rates = Vec::new();
+        for (code, rate) in table {
+            let rate = rate
+                .as_f64()
+                .ok_or_else(|| err(format!("rate of {code} is not a number")))?;
+            rates.push(Rates::check_rate(code, rate).map_err(err)?);
+        }
+        Rates::new(field("date"), field("base"), rates).map_err(err)
+    }
+
+    /// Units of `code` per unit of the base currency.
+    pub fn rate(&self, code: &str) -> Option<f64> {
+        self.rates.iter().find(|(c, _)| c == code).map(|&(_, r)| r)
+    }
+}
+
+/// Reads a rates file, JSON if it ends in `.json` and CSV otherwise, and
+/// makes it the session's rates.
+pub fn load(path: &Path) -> Result<(), RatesError> {
+    let text = fs::read_to_string(path).map_err(|e| RatesError::Io(path.to_path_buf(), e))?;
+    let rates = if path.extension().is_some_and(|ext| ext == "json") {
+        Rates::from_json(&text)?
+    } else {
+        Rates::from_csv(&text)?
+    };
+    set_rates(Some(rates));
+    Ok(())
+}
+
+/// Loads `rates.json` or `rates.csv` from the config dir when there is one.
+/// A file that does not load only matters to conversions with currencies,
+/// so the error is kept for them, see `default_error`.
+pub fn load_default() {
+    if let Err(e) = read_default() {
+        DEFAULT_ERROR.with(|error| *error.borrow_mut() = Some(e.to_string()));
+    }
+}
+
+/// Why the rates file in the config dir did not load, if it did not.
+pub fn default_error() -> Option<String> {
+    DEFAULT_ERROR.with(|error| error.borrow().clone())
+}
+
+fn read_default() -> Result<(), RatesError> {
+    let Some(dir) = config_dir() else {
+        return Ok(());
+    };
+    for name in ["rates.json", "rates.csv"] {
+        let path = dir.join(name);
+        if path.is_file() {
+            return load(&path);
+        }
+    }
+    Ok(())
+}
+
+pub fn set_rates(rates: Option<Rates>) {
+    RATES.with(|r| *r.borrow_mut() = rates);
+}
+
+pub fn rates() -> Option<Rates> {
+    RATES.with(|r| r.borrow().clone())
+}
+
+/// A currency of the loaded rates as a unit. The base currency is the SI
+/// unit of the currency dimension.
+pub fn unit(code: &str) -> Option<UnitDef> {
+    RATES.with(|r| {
+        let rate = r.borrow().as_ref()?.rate(code)?;
+        Some(UnitDef {
+            dim: Dim::currency(1),
+            factor: 1.0 / rate,
+            scale: None,
+            system: None,
+            kind: None,
+            offset: 0.0,
+            exact: None,
+        })
+    })
+}
+
+/// What a conversion involving currencies should say about its rates.
+pub fn note() -> Option<String> {
+    RATES.with(|r| {
+        let rates = r.borrow();
+        let rates = rates.as_ref()?;
+        Some(format!("{} exchange rates of {}", rates.base, rates.date))
+    })
+}
+
+/// Symbol of the base currency, `¤` while no rates are loaded.
+pub fn base_symbol() -> String {
+    RATES.with(|r| {
+        r.borrow()
+            .as_ref()
+            .map_or(String::from("¤"), |rates| rates.base.clone())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_rates() {
+        let rates = Rates::from_csv(
+            "# ECB reference rates\ndate,2026-10-16\nbase,EUR\nUSD,1.0856\nJPY, 162.3\n",
+        )
+        .unwrap();
+        assert_eq!(rates.date, "2026-10-16");
+        assert_eq!(rates.rate("EUR"), Some(1.0));
+        assert_eq!(rates.rate("JPY"), Some(162.3));
+        assert!(matches!(
+            Rates::from_csv("date,2026-10-16\nbase,EUR\nUSD,abc\n"),
+            Err(RatesError::Csv(3, _))
+        ));
+        assert!(matches!(
+            Rates::from_csv("base,EUR\nUSD,1.1\n"),
+            Err(RatesError::Csv(2, _))
+        ));
+        assert!(Rates::from_csv("date,x\nbase,EUR\nusd,1.1\n").is_err());
+        assert!(Rates::from_csv("date,x\nbase,EUR\nUSD,-1\n").is_err());
+    }
+
+    #[test]
+    fn json_rates() {
+        let rates = Rates::from_json(
+            r#"{"date": "2026-10-16", "base": "USD", "rates": {"EUR": 0.92, "USD": 1}}"#,
+        )
+        .unwrap();
+        assert_eq!(rates.base, "USD");
+        assert_eq!(rates.rates.len(), 2);
+        assert_eq!(rates.rate("EUR"), Some(0.92));
+        assert!(Rates::from_json(r#"{"date": "2026-10-16", "base": "USD"}"#).is_err());
+        assert!(Rates::from_json(r#"{"base": "USD", "rates": {}}"#).is_err());
+    }
+
+    #[test]
+    fn default_rates_that_do_not_load() {
+        use crate::parse::{ParseErrorKind, parse_expr};
+        let e = Rates::from_csv("base,EUR
+").unwrap_err();
+        DEFAULT_ERROR.with(|error| *error.borrow_mut() = Some(e.to_string()));
+        assert!(parse_expr("1 m to ft").is_ok());
+        let e = parse_expr("1 USD to EUR").unwrap_err();
+        assert!(matches!(e.kind, ParseErrorKind::RatesNotLoaded("USD", _)), "{e}");
+    }
+}
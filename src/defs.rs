@@ -0,0 +1,697 @@
+use std::f64::consts::PI;
+
+use thiserror::Error;
+
+use crate::constants::Codata;
+use crate::rational::Ratio;
+use crate::systems::UnitSystem;
+use crate::units::{Dim, QuantityKind};
+
+#[derive(Debug, Clone, Error, PartialEq)]
+pub enum DefErrorKind {
+    #[error("{0}")]
+    Syntax(String),
+
+    #[error("unknown unit {0}")]
+    UnknownUnit(String),
+
+    #[error("{0} is defined twice")]
+    Duplicate(String),
+
+    #[error("name {0} already stands for {1}")]
+    NameTaken(String, String),
+
+    #[error("name {0} is already the symbol of a unit, {1}")]
+    NameIsSymbol(String, String),
+
+    #[error("circular definition {0}")]
+    Cycle(String),
+
+    #[error("{0} has dimension {1}, its group {2}")]
+    DimensionMismatch(String, Dim, Dim),
+
+    #[error("unknown quantity kind {0}")]
+    UnknownKind(String),
+
+    #[error("unknown unit system {0}")]
+    UnknownSystem(String),
+
+    #[error("unknown prefix {0}")]
+    UnknownPrefix(String),
+
+    #[error("a CODATA constant may only appear once, as a factor")]
+    Codata,
+}
+
+/// An error in a definitions file, with the line it was found on.
+#[derive(Debug, Clone, Error, PartialEq)]
+#[error("line {line}: {kind}")]
+pub struct DefError {
+    pub line: usize,
+    pub kind: DefErrorKind,
+}
+
+impl DefError {
+    pub fn new(line: usize, kind: DefErrorKind) -> DefError {
+        DefError { line, kind }
+    }
+
+    fn syntax(line: usize, msg: impl Into<String>) -> DefError {
+        DefError::new(line, DefErrorKind::Syntax(msg.into()))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Prefix {
+    pub symbol: &'static str,
+    pub name: &'static str,
+    pub factor: f64,
+}
+
+const fn prefix(symbol: &'static str, name: &'static str, factor: f64) -> Prefix {
+    Prefix {
+        symbol,
+        name,
+        factor,
+    }
+}
+
+pub static PREFIXES: &[Prefix] = &[
+    prefix("Q", "quetta", 1e30),
+    prefix("R", "ronna", 1e27),
+    prefix("Y", "yotta", 1e24),
+    prefix("Z", "zetta", 1e21),
+    prefix("E", "exa", 1e18),
+    prefix("P", "peta", 1e15),
+    prefix("T", "tera", 1e12),
+    prefix("G", "giga", 1e9),
+    prefix("M", "mega", 1e6),
+    prefix("k", "kilo", 1e3),
+    prefix("h", "hecto", 1e2),
+    prefix("da", "deca", 1e1),
+    prefix("d", "deci", 1e-1),
+    prefix("c", "centi", 1e-2),
+    prefix("m", "milli", 1e-3),
+    prefix("u", "micro", 1e-6),
+    prefix("n", "nano", 1e-9),
+    prefix("p", "pico", 1e-12),
+    prefix("f", "femto", 1e-15),
+    prefix("a", "atto", 1e-18),
+    prefix("z", "zepto", 1e-21),
+    prefix("y", "yocto", 1e-24),
+    prefix("r", "ronto", 1e-27),
+    prefix("q", "quecto", 1e-30),
+];
+
+/// `[<name>: <expression>] <key>=<value> ...`
+#[derive(Debug, Clone)]
+pub struct GroupDecl {
+    pub line: usize,
+    pub name: String,
+    /// the expression whose dimension every unit of the group must have
+    pub dim: Option<String>,
+    pub kind: Option<QuantityKind>,
+    pub system: Option<UnitSystem>,
+    pub short: Option<String>,
+}
+
+/// `<symbol> = <expression>` and the indented lines below it.
+#[derive(Debug, Clone)]
+pub struct UnitDecl {
+    pub line: usize,
+    pub symbol: String,
+    pub expr: String,
+    /// index into [`Definitions::groups`]
+    pub group: usize,
+    pub prefixes: Vec<Prefix>,
+    pub names: Vec<String>,
+    pub desc: Option<String>,
+    pub zero: Option<String>,
+    pub local: Vec<String>,
+    /// replaces a unit that already exists
+    pub force: bool,
+}
+
+impl UnitDecl {
+    /// The definition as it is written in a definitions file.
+    pub fn render(&self) -> String {
+        let mut text = format!("{} = {}\n", self.symbol, self.expr);
+        if self.force {
+            text.push_str("    force: yes\n");
+        }
+        if !self.prefixes.is_empty() {
+            let symbols: Vec<&str> = self.prefixes.iter().map(|p| p.symbol).collect();
+            text.push_str(&format!("    prefixes: {}\n", symbols.join(" ")));
+        }
+        if !self.names.is_empty() {
+            text.push_str(&format!("    names: {}\n", self.names.join(", ")));
+        }
+        if let Some(desc) = &self.desc {
+            text.push_str(&format!("    desc: {desc}\n"));
+        }
+        if let Some(zero) = &self.zero {
+            text.push_str(&format!("    zero: {zero}\n"));
+        }
+        text
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Definitions {
+    pub groups: Vec<GroupDecl>,
+    pub units: Vec<UnitDecl>,
+    /// lines of the `[names]` section: (line, symbol, names)
+    pub names: Vec<(usize, String, Vec<String>)>,
+    /// lines of the `[auto]` sections
+    pub auto: Vec<AutoDecl>,
+}
+
+/// `<group>: <symbol>, -<symbol>, ...` in `[auto]` or `[auto <profile>]`:
+/// the units `to auto` picks from in a group, and the ones it never picks.
+#[derive(Debug, Clone)]
+pub struct AutoDecl {
+    pub line: usize,
+    pub profile: Option<String>,
+    pub group: String,
+    pub units: Vec<String>,
+    pub excluded: Vec<String>,
+}
+
+fn list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+fn parse_header(line: usize, header: &str) -> Result<GroupDecl, DefError> {
+    let (inner, attrs) = header[1..]
+        .split_once(']')
+        .ok_or_else(|| DefError::syntax(line, "missing `]`"))?;
+    let (name, dim) = match inner.split_once(':') {
+        Some((name, dim)) => (name.trim(), Some(dim.trim().to_string())),
+        None => (inner.trim(), None),
+    };
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err(DefError::syntax(line, "expected `[<group>: <expression>]`"));
+    }
+    let mut group = GroupDecl {
+        line,
+        name: name.to_string(),
+        dim,
+        kind: None,
+        system: None,
+        short: None,
+    };
+    for attr in attrs.split_whitespace() {
+        let (key, value) = attr.split_once('=').ok_or_else(|| {
+            DefError::syntax(line, format!("expected `<key>=<value>`, found {attr}"))
+        })?;
+        match key {
+            "kind" => {
+                let kind = QuantityKind::from_name(&value.replace('_', " "))
+                    .ok_or_else(|| DefError::new(line, DefErrorKind::UnknownKind(value.into())))?;
+                group.kind = Some(kind);
+            }
+            "system" => {
+                let system = UnitSystem::from_name(value).ok_or_else(|| {
+                    DefError::new(line, DefErrorKind::UnknownSystem(value.into()))
+                })?;
+                group.system = Some(system);
+            }
+            "short" => group.short = Some(value.to_string()),
+            _ => {
+                return Err(DefError::syntax(
+                    line,
+                    format!("unknown group attribute {key}"),
+                ));
+            }
+        }
+    }
+    Ok(group)
+}
+
+fn parse_prefixes(line: usize, value: &str) -> Result<Vec<Prefix>, DefError> {
+    value
+        .split_whitespace()
+        .map(|symbol| {
+            PREFIXES
+                .iter()
+                .find(|p| p.symbol == symbol)
+                .copied()
+                .ok_or_else(|| DefError::new(line, DefErrorKind::UnknownPrefix(symbol.into())))
+        })
+        .collect()
+}
+
+/// Reads the lines of a definitions file, see `units.def` for the format.
+/// Only the syntax is checked here; what the names refer to is resolved by
+/// the registry.
+pub fn parse(text: &str) -> Result<Definitions, DefError> {
+    let mut defs = Definitions::default();
+    let mut in_names = false;
+    // `Some(profile)` inside `[auto <profile>]`, `Some(None)` inside `[auto]`
+    let mut in_auto: Option<Option<String>> = None;
+    // whether indented lines have a unit to describe
+    let mut has_unit = false;
+    for (i, raw) in text.lines().enumerate() {
+        let line = i + 1;
+        let content = raw.split('#').next().unwrap();
+        if content.trim().is_empty() {
+            continue;
+        }
+        if content.starts_with(char::is_whitespace) {
+            let Some(unit) = defs.units.last_mut().filter(|_| has_unit) else {
+                return Err(DefError::syntax(line, "indented line without a unit above"));
+            };
+            let (key, value) = content
+                .split_once(':')
+                .map(|(k, v)| (k.trim(), v.trim()))
+                .ok_or_else(|| DefError::syntax(line, "expected `<key>: <value>`"))?;
+            match key {
+                "prefixes" => unit.prefixes.extend(parse_prefixes(line, value)?),
+                "names" => unit.names.extend(list(value)),
+                "desc" => unit.desc = Some(value.to_string()),
+                "zero" => unit.zero = Some(value.to_string()),
+                "local" => unit.local.extend(list(value)),
+                "force" => {
+                    unit.force = match value {
+                        "yes" => true,
+                        "no" => false,
+                        _ => {
+                            return Err(DefError::syntax(
+                                line,
+                                "expected `force: yes` or `force: no`",
+                            ));
+                        }
+                    }
+                }
+                _ => return Err(DefError::syntax(line, format!("unknown key {key}"))),
+            }
+            continue;
+        }
+        let content = content.trim();
+        has_unit = false;
+        let auto_profile = content
+            .strip_prefix("[auto")
+            .and_then(|rest| rest.strip_suffix(']'))
+            .filter(|rest| rest.is_empty() || rest.starts_with(' '))
+            .map(str::trim);
+        if content == "[names]" {
+            in_names = true;
+            in_auto = None;
+        } else if let Some(profile) = auto_profile {
+            in_names = false;
+            in_auto = Some((!profile.is_empty()).then(|| profile.to_string()));
+        } else if content.starts_with('[') {
+            in_names = false;
+            in_auto = None;
+            defs.groups.push(parse_header(line, content)?);
+        } else if let Some(profile) = &in_auto {
+            let (group, symbols) = content
+                .split_once(':')
+                .ok_or_else(|| DefError::syntax(line, "expected `<group>: <symbol>, ...`"))?;
+            let (excluded, units): (Vec<String>, Vec<String>) =
+                list(symbols).into_iter().partition(|s| s.starts_with('-'));
+            defs.auto.push(AutoDecl {
+                line,
+                profile: profile.clone(),
+                group: group.trim().to_string(),
+                units,
+                excluded: excluded.iter().map(|s| s[1..].to_string()).collect(),
+            });
+        } else if in_names {
+            let (symbol, names) = content
+                .split_once(':')
+                .ok_or_else(|| DefError::syntax(line, "expected `<symbol>: <name>, ...`"))?;
+            defs.names
+                .push((line, symbol.trim().to_string(), list(names)));
+        } else {
+            let (symbol, expr) = content
+                .split_once('=')
+                .map(|(s, e)| (s.trim(), e.trim()))
+                .ok_or_else(|| DefError::syntax(line, "expected `<symbol> = <expression>`"))?;
+            if symbol.is_empty() || symbol.contains(char::is_whitespace) || expr.is_empty() {
+                return Err(DefError::syntax(line, "expected `<symbol> = <expression>`"));
+            }
+            if defs.groups.is_empty() {
+                return Err(DefError::syntax(line, "unit outside of a group"));
+            }
+            defs.units.push(UnitDecl {
+                line,
+                symbol: symbol.to_string(),
+                expr: expr.to_string(),
+                group: defs.groups.len() - 1,
+                prefixes: Vec::new(),
+                names: Vec::new(),
+                desc: None,
+                zero: None,
+                local: Vec::new(),
+                force: false,
+            });
+            has_unit = true;
+        }
+    }
+    Ok(defs)
+}
+
+/// The SI value of a definition's right-hand side.
+#[derive(Debug, Clone, Copy)]
+pub struct Value {
+    pub factor: f64,
+    pub dim: Dim,
+    /// a CODATA constant the factor is multiplied with
+    pub scale: Option<fn(&Codata) -> f64>,
+    /// the factor as a fraction, while every number involved is exact
+    pub exact: Option<Ratio>,
+}
+
+impl Value {
+    pub fn number(factor: f64) -> Value {
+        Value {
+            factor,
+            dim: Dim::zero(),
+            scale: None,
+            exact: None,
+        }
+    }
+
+    pub fn exact(factor: Ratio) -> Value {
+        Value {
+            exact: Some(factor),
+            ..Value::number(factor.to_f64())
+        }
+    }
+
+    fn mul(self, other: Value) -> Result<Value, DefErrorKind> {
+        if self.scale.is_some() && other.scale.is_some() {
+            return Err(DefErrorKind::Codata);
+        }
+        let mut dim = self.dim;
+        dim.mul(other.dim);
+        Ok(Value {
+            factor: self.factor * other.factor,
+            dim,
+            scale: self.scale.or(other.scale),
+            exact: self.exact.zip(other.exact).and_then(|(a, b)| a.mul(b)),
+        })
+    }
+
+    fn div(self, other: Value) -> Result<Value, DefErrorKind> {
+        if other.scale.is_some() {
+            return Err(DefErrorKind::Codata);
+        }
+        let mut dim = self.dim;
+        dim.div(other.dim);
+        Ok(Value {
+            factor: self.factor / other.factor,
+            dim,
+            scale: self.scale,
+            exact: self.exact.zip(other.exact).and_then(|(a, b)| a.div(b)),
+        })
+    }
+
+    fn pow(self, k: i32) -> Result<Value, DefErrorKind> {
+        if self.scale.is_some() && k != 1 {
+            return Err(DefErrorKind::Codata);
+        }
+        let mut dim = self.dim;
+        dim.pow(k);
+        Ok(Value {
+            factor: self.factor.powi(k),
+            dim,
+            scale: self.scale,
+            exact: self.exact.and_then(|r| r.pow(k)),
+        })
+    }
+}
+
+fn base_unit(name: &str) -> Option<Dim> {
+    Some(match name {
+        "length" => Dim::len(1),
+        "mass" => Dim::mass(1),
+        "time" => Dim::time(1),
+        "temperature" => Dim::temp(1),
+        "current" => Dim::curr(1),
+        "luminous_intensity" => Dim::lum(1),
+        "amount" => Dim::amount(1),
+        _ => return None,
+    })
+}
+
+fn constant(name: &str) -> Option<Value> {
+    let (dim, scale): (Dim, fn(&Codata) -> f64) = match name {
+        "codata.e" => (Dim::new(0, 0, 1, 0, 1), |c| c.e),
+        "codata.amu" => (Dim::mass(1), |c| c.amu),
+        _ => return None,
+    };
+    Some(Value {
+        factor: 1.0,
+        dim,
+        scale: Some(scale),
+        exact: None,
+    })
+}
+
+fn is_name_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_' || c == '%'
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '.'
+}
+
+struct Eval<'a, 'f> {
+    s: &'a str,
+    pos: usize,
+    line: usize,
+    lookup: &'f mut dyn FnMut(&str) -> Result<Value, DefError>,
+}
+
+impl Eval<'_, '_> {
+    fn peek(&self) -> Option<char> {
+        self.s[self.pos..].chars().next()
+    }
+
+    fn skip_ws(&mut self) {
+        while let Some(c) = self.peek()
+            && c.is_whitespace()
+        {
+            self.pos += c.len_utf8();
+        }
+    }
+
+    fn err<T>(&self, kind: DefErrorKind) -> Result<T, DefError> {
+        Err(DefError::new(self.line, kind))
+    }
+
+    fn syntax<T>(&self, msg: &str) -> Result<T, DefError> {
+        self.err(DefErrorKind::Syntax(format!(
+            "{msg} at column {}",
+            self.pos + 1
+        )))
+    }
+
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> &str {
+        let start = self.pos;
+        while let Some(c) = self.peek()
+            && f(c)
+        {
+            self.pos += c.len_utf8();
+        }
+        &self.s[start..self.pos]
+    }
+
+    fn expr(&mut self) -> Result<Value, DefError> {
+        let mut value = self.term()?;
+        loop {
+            self.skip_ws();
+            let result = match self.peek() {
+                Some('*') => {
+                    self.pos += 1;
+                    value.mul(self.term()?)
+                }
+                Some('/') => {
+                    self.pos += 1;
+                    value.div(self.term()?)
+                }
+                // juxtaposition, as in `5/9 K`
+                Some(c)
+                    if c.is_ascii_digit()
+                        || c == '.'
+                        || c == '('
+                        || c == '!'
+                        || is_name_start(c) =>
+                {
+                    value.mul(self.term()?)
+                }
+                _ => return Ok(value),
+            };
+            value = result.or_else(|kind| self.err(kind))?;
+        }
+    }
+
+    fn term(&mut self) -> Result<Value, DefError> {
+        let value = self.atom()?;
+        self.skip_ws();
+        if self.peek() != Some('^') {
+            return Ok(value);
+        }
+        self.pos += 1;
+        self.skip_ws();
+        let start = self.pos;
+        if self.peek() == Some('-') {
+            self.pos += 1;
+        }
+        self.take_while(|c| c.is_ascii_digit());
+        let Ok(k) = self.s[start..self.pos].parse::<i32>() else {
+            return self.syntax("expected an integer exponent");
+        };
+        value.pow(k).or_else(|kind| self.err(kind))
+    }
+
+    fn atom(&mut self) -> Result<Value, DefError> {
+        self.skip_ws();
+        match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                let value = self.expr()?;
+                self.skip_ws();
+                if self.peek() != Some(')') {
+                    return self.syntax("expected `)`");
+                }
+                self.pos += 1;
+                Ok(value)
+            }
+            Some('!') => {
+                self.pos += 1;
+                let name = self.take_while(|c| c.is_alphanumeric() || c == '_');
+                match base_unit(name) {
+                    Some(dim) => Ok(Value {
+                        dim,
+                        ..Value::exact(Ratio::ONE)
+                    }),
+                    None => self.syntax("unknown base dimension"),
+                }
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => self.number(),
+            Some('%') => {
+                self.pos += 1;
+                (self.lookup)("%")
+            }
+            Some(c) if is_name_start(c) => {
+                let name = self.take_while(is_name_char).to_string();
+                if name == "pi" {
+                    return Ok(Value::number(PI));
+                }
+                match constant(&name) {
+                    Some(value) => Ok(value),
+                    None => (self.lookup)(&name),
+                }
+            }
+            _ => self.syntax("expected a number or a unit"),
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, DefError> {
+        let start = self.pos;
+        self.take_while(|c| c.is_ascii_digit() || c == '.');
+        if matches!(self.peek(), Some('e' | 'E')) {
+            let mantissa_end = self.pos;
+            self.pos += 1;
+            if matches!(self.peek(), Some('+' | '-')) {
+                self.pos += 1;
+            }
+            if self.take_while(|c| c.is_ascii_digit()).is_empty() {
+                self.pos = mantissa_end;
+            }
+        }
+        let text = &self.s[start..self.pos];
+        match text.parse::<f64>() {
+            Ok(n) => Ok(Value {
+                exact: Ratio::parse(text),
+                ..Value::number(n)
+            }),
+            Err(_) => self.syntax("invalid number"),
+        }
+    }
+}
+
+/// Evaluates the right-hand side of a definition. `lookup` gives the value
+/// of the units it mentions, `line` is where syntax errors are reported.
+pub fn eval(
+    expr: &str,
+    line: usize,
+    lookup: &mut dyn FnMut(&str) -> Result<Value, DefError>,
+) -> Result<Value, DefError> {
+    let mut eval = Eval {
+        s: expr,
+        pos: 0,
+        line,
+        lookup,
+    };
+    let value = eval.expr()?;
+    eval.skip_ws();
+    if eval.pos < expr.len() {
+        return eval.syntax("unexpected character");
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(expr: &str) -> Result<Value, DefError> {
+        eval(expr, 1, &mut |name| match name {
+            "m" => Ok(Value {
+                dim: Dim::len(1),
+                ..Value::number(1.0)
+            }),
+            "s" => Ok(Value {
+                dim: Dim::time(1),
+                ..Value::number(1.0)
+            }),
+            _ => Err(DefError::new(1, DefErrorKind::UnknownUnit(name.into()))),
+        })
+    }
+
+    #[test]
+    fn expressions() {
+        let v = value("5/9 m").unwrap();
+        assert_eq!((v.factor, v.dim), (5.0 / 9.0, Dim::len(1)));
+        let v = value("1e3 m^2/(4 s)").unwrap();
+        assert_eq!((v.factor, v.dim), (250.0, Dim::new(2, 0, -1, 0, 0)));
+        let v = value("1/pi m s^-1").unwrap();
+        assert_eq!((v.factor, v.dim), (1.0 / PI, Dim::new(1, 0, -1, 0, 0)));
+        assert!(value("codata.e m").unwrap().scale.is_some());
+        assert_eq!(value("!length").unwrap().dim, Dim::len(1));
+        assert_eq!(value("m^x").unwrap_err().line, 1);
+        assert!(value("(m").is_err());
+        assert!(value("1/codata.e").is_err());
+        assert!(matches!(
+            value("2 ft").unwrap_err().kind,
+            DefErrorKind::UnknownUnit(u) if u == "ft"
+        ));
+    }
+
+    #[test]
+    fn file_syntax() {
+        let defs = parse("[length: m] kind=length\nm = !length # base\n    prefixes: k c\n\n[names]\nkm: 公里\n").unwrap();
+        assert_eq!(defs.groups[0].kind, Some(QuantityKind::Length));
+        assert_eq!(defs.units[0].prefixes.len(), 2);
+        assert_eq!(defs.names[0].2, ["公里"]);
+        let defs = parse("[auto]\ntime: s, hour, -ks\n[auto chemistry]\nlength: ang\n").unwrap();
+        assert_eq!(defs.auto[0].units, ["s", "hour"]);
+        assert_eq!(defs.auto[0].excluded, ["ks"]);
+        assert_eq!(defs.auto[1].profile.as_deref(), Some("chemistry"));
+        let line = |text: &str| parse(text).unwrap_err().line;
+        assert_eq!(line("m = !length\n"), 1);
+        assert_eq!(line("[a: m]\nm = !length\n  prefixes: x\n"), 3);
+        assert_eq!(line("[a: m] kind=mass\n"), 1);
+        assert_eq!(line("[a]\n\n  names: x\n"), 3);
+        assert_eq!(line("[a]\nm !length\n"), 2);
+    }
+}
@@ -0,0 +1,206 @@
+// Calendar durations use the mean Gregorian year and month, so that they can
+// be converted to and from seconds without a reference date.
+pub const YEAR: f64 = 31_556_952.0; // 365.2425 day
+pub const MONTH: f64 = YEAR / 12.0;
+pub const WEEK: f64 = 604_800.0;
+pub const DAY: f64 = 86_400.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DurationStyle {
+    /// `2y 3mo 4d 05:06:07`
+    Human,
+    /// `P2Y3M4DT5H6M7S`
+    Iso8601,
+}
+
+impl DurationStyle {
+    pub fn from_name(name: &str) -> Option<DurationStyle> {
+        match name {
+            "duration" => Some(DurationStyle::Human),
+            "iso8601" => Some(DurationStyle::Iso8601),
+            _ => None,
+        }
+    }
+}
+
+/// Parses an ISO-8601 duration such as `P1Y2M3DT4H5M6.5S` or `P2W` into seconds.
+pub fn parse_iso8601(s: &str) -> Option<f64> {
+    let (sign, s) = match s.strip_prefix('-') {
+        Some(rest) => (-1.0, rest),
+        None => (1.0, s),
+    };
+    let s = s.strip_prefix('P')?;
+    let (date, time) = match s.split_once('T') {
+        Some((date, time)) if !time.is_empty() => (date, Some(time)),
+        Some(_) => return None,
+        None => (s, None),
+    };
+    if date.is_empty() && time.is_none() {
+        return None;
+    }
+    let date_units: &[(char, f64)] = &[('Y', YEAR), ('M', MONTH), ('W', WEEK), ('D', DAY)];
+    let time_units: &[(char, f64)] = &[('H', 3600.0), ('M', 60.0), ('S', 1.0)];
+    let mut total = parse_designators(date, date_units)?;
+    if let Some(time) = time {
+        total += parse_designators(time, time_units)?;
+    }
+    Some(sign * total)
+}
+
+// `units` lists the designators in the order they must appear
+fn parse_designators(mut s: &str, units: &[(char, f64)]) -> Option<f64> {
+    let mut total = 0.0;
+    let mut next = 0;
+    while !s.is_empty() {
+        let end = s.find(|c: char| !(c.is_ascii_digit() || c == '.' || c == ','))?;
+        let number: f64 = s[..end].replace(',', ".").parse().ok()?;
+        let designator = s[end..].chars().next()?;
+        let idx = next + units[next..].iter().position(|&(d, _)| d == designator)?;
+        total += number * units[idx].1;
+        next = idx + 1;
+        s = &s[end + designator.len_utf8()..];
+    }
+    Some(total)
+}
+
+struct Parts {
+    negative: bool,
+    years: u64,
+    months: u64,
+    days: u64,
+    hours: u64,
+    minutes: u64,
+    // microseconds within the minute
+    micros: u64,
+}
+
+impl Parts {
+    /// `None` past some 584 000 years, where the microseconds overflow
+    fn split(seconds: f64) -> Option<Parts> {
+        const US: f64 = 1e6;
+        let micros = (seconds.abs() * US).round();
+        if micros.is_nan() || micros >= u64::MAX as f64 {
+            return None;
+        }
+        let mut rest = micros as u64;
+        let mut take = |unit: f64| {
+            let unit = (unit * US) as u64;
+            let n = rest / unit;
+            rest %= unit;
+            n
+        };
+        let years = take(YEAR);
+        let months = take(MONTH);
+        let days = take(DAY);
+        let hours = take(3600.0);
+        let minutes = take(60.0);
+        Some(Parts {
+            negative: seconds < 0.0,
+            years,
+            months,
+            days,
+            hours,
+            minutes,
+            micros: rest,
+        })
+    }
+
+    fn seconds(&self) -> String {
+        let whole = self.micros / 1_000_000;
+        let frac = self.micros % 1_000_000;
+        if frac == 0 {
+            format!("{whole:02}")
+        } else {
+            let frac = format!("{frac:06}");
+            format!("{whole:02}.{}", frac.trim_end_matches('0'))
+        }
+    }
+}
+
+/// `seconds` as a duration, or `None` when it is too long to write as one.
+pub fn format(seconds: f64, style: DurationStyle) -> Option<String> {
+    let p = Parts::split(seconds)?;
+    let mut out = String::new();
+    if p.negative {
+        out.push('-');
+    }
+    match style {
+        DurationStyle::Human => {
+            for (n, suffix) in [(p.years, "y"), (p.months, "mo"), (p.days, "d")] {
+                if n != 0 {
+                    out.push_str(format!("{n}{suffix} ").as_str());
+                }
+            }
+            out.push_str(format!("{:02}:{:02}:{}", p.hours, p.minutes, p.seconds()).as_str());
+        }
+        DurationStyle::Iso8601 => {
+            out.push('P');
+            for (n, designator) in [(p.years, 'Y'), (p.months, 'M'), (p.days, 'D')] {
+                if n != 0 {
+                    out.push_str(format!("{n}{designator}").as_str());
+                }
+            }
+            let mut time = String::new();
+            for (n, designator) in [(p.hours, 'H'), (p.minutes, 'M')] {
+                if n != 0 {
+                    time.push_str(format!("{n}{designator}").as_str());
+                }
+            }
+            if p.micros != 0 {
+                let secs = p.seconds();
+                let secs = secs
+                    .strip_prefix('0')
+                    .filter(|s| !s.starts_with('.'))
+                    .unwrap_or(&secs);
+                time.push_str(format!("{secs}S").as_str());
+            }
+            if !time.is_empty() {
+                out.push('T');
+                out.push_str(&time);
+            } else if out.ends_with('P') {
+                out.push_str("T0S");
+            }
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_iso() {
+        assert_eq!(parse_iso8601("PT1H30M"), Some(5400.0));
+        assert_eq!(parse_iso8601("P1DT0.5S"), Some(86_400.5));
+        assert_eq!(parse_iso8601("P2W"), Some(2.0 * WEEK));
+        assert_eq!(parse_iso8601("P1Y2M"), Some(YEAR + 2.0 * MONTH));
+        assert_eq!(parse_iso8601("-PT1M"), Some(-60.0));
+        assert_eq!(parse_iso8601("P"), None);
+        assert_eq!(parse_iso8601("PT"), None);
+        assert_eq!(parse_iso8601("P1H"), None);
+        assert_eq!(parse_iso8601("PT1S2M"), None);
+    }
+
+    #[test]
+    fn format_styles() {
+        let t = 2.0 * YEAR + 3.0 * MONTH + 4.0 * DAY + 5.0 * 3600.0 + 6.0 * 60.0 + 7.0;
+        let human = |t| format(t, DurationStyle::Human).unwrap();
+        let iso = |t| format(t, DurationStyle::Iso8601).unwrap();
+        assert_eq!(human(t), "2y 3mo 4d 05:06:07");
+        assert_eq!(iso(t), "P2Y3M4DT5H6M7S");
+        assert_eq!(human(90.5), "00:01:30.5");
+        assert_eq!(iso(90.5), "PT1M30.5S");
+        assert_eq!(iso(0.0), "PT0S");
+        assert_eq!(human(-3600.0), "-01:00:00");
+    }
+
+    #[test]
+    fn out_of_range() {
+        assert_eq!(format(1e30, DurationStyle::Human), None);
+        assert_eq!(format(-1e30, DurationStyle::Iso8601), None);
+        assert_eq!(format(f64::NAN, DurationStyle::Human), None);
+        assert_eq!(format(f64::INFINITY, DurationStyle::Human), None);
+        assert_eq!(format(1e13, DurationStyle::Human).as_deref(), Some("316887y 4mo 18d 21:29:52"));
+    }
+}
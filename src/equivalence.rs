@@ -0,0 +1,121 @@
+use crate::settings::Settings;
+use crate::units::{Dim, QuantityKind};
+
+/// A physical relation between two dimensions, e.g. luminous efficacy
+/// between radiant and luminous flux. Unlike a unit conversion it only
+/// holds under an assumption, so it is applied only when asked for.
+#[derive(Debug, Clone)]
+pub struct Equivalence {
+    pub description: String,
+    pub from: Dim,
+    pub to: Dim,
+    /// SI value of `to` per SI value of `from`
+    pub factor: f64,
+    /// kinds the `to` side may be of, when not all of them: `lm` and `cd`
+    /// share a dimension, but only lumens are linked to watts
+    pub to_kinds: Option<&'static [QuantityKind]>,
+}
+
+/// An equivalence resolved for one conversion.
+#[derive(Debug, Clone)]
+pub struct Bridge {
+    pub equivalence: Equivalence,
+    /// multiplies the SI value of the source quantity
+    pub factor: f64,
+}
+
+fn ratio(a: Dim, b: Dim) -> Dim {
+    let mut d = a;
+    d.div(b);
+    d
+}
+
+impl Equivalence {
+    /// Factor that turns an SI value of `from` into one of `to`, when this
+    /// equivalence links them. Compound dimensions are linked too, e.g.
+    /// W/m^2 to lx by luminous efficacy. A side of no kind fits any.
+    pub fn factor_between(
+        &self,
+        from: (Dim, Option<QuantityKind>),
+        to: (Dim, Option<QuantityKind>),
+    ) -> Option<f64> {
+        let fits = |kind: Option<QuantityKind>| match (kind, self.to_kinds) {
+            (Some(kind), Some(kinds)) => kinds.contains(&kind),
+            _ => true,
+        };
+        let r = ratio(from.0, to.0);
+        if r == ratio(self.from, self.to) && fits(to.1) {
+            Some(self.factor)
+        } else if r == ratio(self.to, self.from) && fits(from.1) {
+            Some(1.0 / self.factor)
+        } else {
+            None
+        }
+    }
+}
+
+/// Equivalences switched on by the settings.
+pub fn active(settings: &Settings) -> Vec<Equivalence> {
+    let mut list = Vec::new();
+    if let Some(efficacy) = settings.efficacy {
+        list.push(Equivalence {
+            description: format!("luminous efficacy {efficacy} lm/W"),
+            from: Dim::new(2, 1, -3, 0, 0),
+            to: Dim::lum(1),
+            factor: efficacy,
+            // `W/sr` is no kind of its own, so `cd` is left out
+            to_kinds: Some(&[QuantityKind::LuminousFlux, QuantityKind::Illuminance]),
+        });
+    }
+    list
+}
+
+pub fn find_bridge(
+    equivalences: &[Equivalence],
+    from: (Dim, Option<QuantityKind>),
+    to: (Dim, Option<QuantityKind>),
+) -> Option<Bridge> {
+    equivalences.iter().find_map(|e| {
+        e.factor_between(from, to).map(|factor| Bridge {
+            equivalence: e.clone(),
+            factor,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compound_dimensions_are_linked() {
+        let e = Equivalence {
+            description: String::new(),
+            from: Dim::new(2, 1, -3, 0, 0),
+            to: Dim::lum(1),
+            factor: 683.0,
+            to_kinds: None,
+        };
+        let mut w_per_m2 = e.from;
+        w_per_m2.div(Dim::len(2));
+        let mut lx = e.to;
+        lx.div(Dim::len(2));
+        assert_eq!(e.factor_between((w_per_m2, None), (lx, None)), Some(683.0));
+        assert_eq!(e.factor_between((lx, None), (w_per_m2, None)), Some(1.0 / 683.0));
+        assert_eq!(e.factor_between((e.from, None), (Dim::len(1), None)), None);
+    }
+
+    #[test]
+    fn kinds_are_matched() {
+        let e = &active(&Settings {
+            efficacy: Some(683.0),
+            ..Settings::default()
+        })[0];
+        let lm = Some(QuantityKind::LuminousFlux);
+        let cd = Some(QuantityKind::LuminousIntensity);
+        assert_eq!(e.factor_between((e.from, None), (e.to, lm)), Some(683.0));
+        assert_eq!(e.factor_between((e.to, lm), (e.from, None)), Some(1.0 / 683.0));
+        assert_eq!(e.factor_between((e.from, None), (e.to, cd)), None);
+        assert_eq!(e.factor_between((e.to, cd), (e.from, None)), None);
+    }
+}
@@ -0,0 +1,262 @@
+use std::fmt::{self, Display};
+
+use crate::convert::{ConversionResult, convert};
+use crate::parse::{Atom, ConversionExpr, UnitTarget};
+use crate::rational::Ratio;
+use crate::render::{render, result_json};
+use crate::settings::settings;
+use crate::systems::UnitSystem;
+use crate::units::Dim;
+
+/// One side of a conversion, as `parse_unit_atom` resolved it.
+#[derive(Debug)]
+pub struct Side {
+    pub symbol: String,
+    /// empty for `au` and unit systems, which are not made of units
+    pub atoms: Vec<Atom>,
+    /// SI value of one unit of the whole expression
+    pub factor: f64,
+    /// SI value of the zero of a temperature scale
+    pub offset: f64,
+    pub dim: Dim,
+    /// what `convert` multiplies `factor` by to match the other side, and why
+    pub scale: Option<(f64, String)>,
+}
+
+/// The steps behind a conversion, for `explain`.
+#[derive(Debug)]
+pub struct Explanation {
+    pub value: f64,
+    pub from: Side,
+    pub to: Side,
+    /// how the dimensions of the two sides were matched
+    pub check: String,
+    /// `from_factor / to_factor`, what the value is multiplied by
+    pub ratio: f64,
+    /// the ratio as a fraction, when every factor is exact
+    pub exact_ratio: Option<Ratio>,
+    /// measured constants the factors depend on
+    pub constants: Vec<String>,
+    pub result: ConversionResult,
+}
+
+impl Side {
+    fn new(target: &UnitTarget, symbol: &str, factor: f64, dim: Dim) -> Side {
+        match target {
+            UnitTarget::Unit(expr) => Side {
+                symbol: expr.symbol.clone(),
+                atoms: expr.atoms.clone(),
+                factor: expr.factor,
+                offset: expr.offset,
+                dim: expr.dim,
+                scale: None,
+            },
+            _ => Side {
+                symbol: symbol.to_string(),
+                atoms: Vec::new(),
+                factor,
+                offset: 0.0,
+                dim,
+                scale: None,
+            },
+        }
+    }
+
+    fn json(&self) -> serde_json::Value {
+        let atoms: Vec<_> = self
+            .atoms
+            .iter()
+            .map(|atom| {
+                serde_json::json!({
+                    "unit": atom.symbol,
+                    "power": atom.power,
+                    "factor": atom.factor,
+                    "dim": atom.dim.to_string(),
+                    "measured": atom.measured,
+                })
+            })
+            .collect();
+        serde_json::json!({
+            "unit": self.symbol,
+            "atoms": atoms,
+            "factor": self.factor,
+            "offset": self.offset,
+            "dim": self.dim.to_string(),
+            "scale": self.scale.as_ref().map(|(factor, reason)| {
+                serde_json::json!({ "factor": factor, "reason": reason })
+            }),
+        })
+    }
+}
+
+pub fn explain(expr: &ConversionExpr) -> Explanation {
+    let settings = settings();
+    let result = convert(expr);
+    let codata = settings.codata.year;
+
+    let from_dim = match &expr.from {
+        UnitTarget::Unit(from) => from.dim,
+        _ => result.dim,
+    };
+    let mut from = Side::new(&expr.from, "au", result.from_factor, from_dim);
+    let mut to = Side::new(&result.target, &result.symbol, result.to_factor, result.dim);
+    if let UnitTarget::Duration(_) = result.target {
+        to.symbol = String::from("s");
+    }
+
+    let mut constants = Vec::new();
+    let mut check = if from.dim == to.dim {
+        format!("{} on both sides", from.dim)
+    } else {
+        format!("{} to {}", from.dim, to.dim)
+    };
+    // convert folds equivalences and unit systems into the factors
+    let from_scale = result.from_factor / from.factor;
+    let to_scale = result.to_factor / to.factor;
+    if let Some(bridge) = &expr.bridge {
+        check.push_str(&format!(", linked by {}", bridge.equivalence.description));
+        from.scale = Some((bridge.factor, bridge.equivalence.description.clone()));
+    } else if let UnitTarget::System(system) = result.target {
+        check = format!("{} in {system} units is {}", from.dim, result.symbol);
+        from.scale = Some((from_scale, format!("SI to {system} units")));
+        if system != UnitSystem::Si {
+            constants.push(format!("{system} units from CODATA {codata}"));
+        }
+    } else if from.dim != to.dim {
+        let system = settings.system;
+        check.push_str(&format!(", the same quantity in {system} units"));
+        from.scale = Some((from_scale, format!("SI to {system} units")));
+        to.scale = Some((to_scale, format!("SI to {system} units")));
+        constants.push(format!("{system} units from CODATA {codata}"));
+    }
+
+    for atom in from.atoms.iter().chain(&to.atoms) {
+        let constant = format!("{} from CODATA {codata}", atom.symbol);
+        if atom.measured && !constants.contains(&constant) {
+            constants.push(constant);
+        }
+    }
+    if matches!(expr.from, UnitTarget::Au) || matches!(result.target, UnitTarget::Au) {
+        constants.push(format!("atomic units from CODATA {codata}"));
+    }
+
+    let exact_ratio = match (&expr.from, &result.target) {
+        (UnitTarget::Unit(from), UnitTarget::Unit(to))
+            if expr.bridge.is_none() && from.dim == to.dim =>
+        {
+            from.exact
+                .zip(to.exact)
+                .and_then(|(from, to)| from.factor.div(to.factor))
+        }
+        _ => None,
+    };
+    Explanation {
+        value: expr.value,
+        from,
+        to,
+        check,
+        ratio: result.from_factor / result.to_factor,
+        exact_ratio,
+        constants,
+        result,
+    }
+}
+
+impl Explanation {
+    /// The same steps as JSON, for scripts.
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "value": self.value,
+            "from": self.from.json(),
+            "to": self.to.json(),
+            "dimension": self.check,
+            "ratio": self.ratio,
+            "exact_ratio": self.exact_ratio.map(|ratio| ratio.to_string()),
+            "constants": self.constants,
+            "result": result_json(&self.result),
+        })
+    }
+}
+
+impl Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut buffer = ryu::Buffer::new();
+        for (label, side) in [("from", &self.from), ("to", &self.to)] {
+            writeln!(f, "{label} {}", side.symbol)?;
+            // each row is the unit raised to its power, as it enters `=`
+            for atom in &side.atoms {
+                let symbol = match atom.power {
+                    1 => atom.symbol.clone(),
+                    k => format!("{}^{k}", atom.symbol),
+                };
+                let mut dim = atom.dim;
+                dim.pow(atom.power);
+                writeln!(
+                    f,
+                    "  {symbol:<12} {:<22} {dim}",
+                    buffer.format(atom.factor.powi(atom.power)),
+                )?;
+            }
+            writeln!(
+                f,
+                "  {:<12} {:<22} {}",
+                "=",
+                buffer.format(side.factor),
+                side.dim
+            )?;
+            if side.offset != 0.0 {
+                writeln!(f, "  zero at {} K", buffer.format(side.offset))?;
+            }
+            if let Some((factor, reason)) = &side.scale {
+                writeln!(f, "  × {} ({reason})", buffer.format(*factor))?;
+            }
+        }
+        writeln!(f, "dimension: {}", self.check)?;
+        write!(f, "ratio: {}", buffer.format(self.ratio))?;
+        match self.exact_ratio {
+            Some(ratio) => writeln!(f, " = {ratio}")?,
+            None => writeln!(f)?,
+        }
+        for constant in &self.constants {
+            writeln!(f, "constant: {constant}")?;
+        }
+        write!(f, "result: {}", render(&self.result))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::parse_expr;
+
+    #[test]
+    fn kwh_per_day() {
+        let explanation = explain(&parse_expr("1 kWh/day to W").unwrap());
+        let atoms: Vec<_> = explanation
+            .from
+            .atoms
+            .iter()
+            .map(|atom| (atom.symbol.as_str(), atom.power, atom.factor))
+            .collect();
+        assert_eq!(atoms, [("kWh", 1, 3.6e6), ("day", -1, 86_400.0)]);
+        assert_eq!(explanation.to.factor, 1.0);
+        assert_eq!(explanation.exact_ratio.unwrap().to_string(), "125/3");
+        let text = explanation.to_string();
+        assert!(text.contains("  day^-1       0.000011574074074074073 T^-1"), "{text}");
+        assert!(text.ends_with("result: 41.666666666666664 W"), "{text}");
+        let json = explanation.json();
+        assert_eq!(json["from"]["atoms"][1]["power"], -1);
+        assert_eq!(json["result"]["unit"], "W");
+    }
+
+    #[test]
+    fn equivalences_and_constants() {
+        let explanation = explain(&parse_expr("2 cup of flour to g").unwrap());
+        assert!(explanation.check.contains("linked by"), "{}", explanation.check);
+        assert!(explanation.from.scale.is_some());
+        assert_eq!(explanation.exact_ratio, None);
+
+        let explanation = explain(&parse_expr("1 eV to J").unwrap());
+        assert_eq!(explanation.constants, ["eV from CODATA 2022"]);
+    }
+}
@@ -0,0 +1,522 @@
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::defs::{self, Definitions, GroupDecl, PREFIXES, UnitDecl, Value};
+use crate::registry::{self, Registry, Source, is_valid_symbol};
+
+#[derive(Debug, Error)]
+pub enum ImportError {
+    #[error("Cannot read {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+}
+
+/// What came of importing a file.
+#[derive(Debug)]
+pub struct ImportReport {
+    pub path: PathBuf,
+    pub units: usize,
+    /// lines that were left out, with the reason
+    pub skipped: Vec<(usize, String)>,
+}
+
+impl Display for ImportReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = self.path.display();
+        write!(f, "Imported {} units from {path}", self.units)?;
+        for (line, reason) in &self.skipped {
+            write!(f, "\n{path}:{line}: {reason}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Adds the units of a GNU `units` or a pint definitions file to the
+/// session's registry. Prefixes, linear definitions, aliases and comments
+/// are understood; whatever else is in the file is reported and left out.
+pub fn import(path: &Path) -> Result<ImportReport, ImportError> {
+    let text = fs::read_to_string(path).map_err(|e| ImportError::Io(path.to_path_buf(), e))?;
+    let source = Source::Import(path.to_path_buf());
+    let (units, skipped) = registry::with_mut(|r| import_text(r, &text, source));
+    Ok(ImportReport {
+        path: path.to_path_buf(),
+        units,
+        skipped,
+    })
+}
+
+fn import_text(
+    registry: &mut Registry,
+    text: &str,
+    source: Source,
+) -> (usize, Vec<(usize, String)>) {
+    let file = if is_pint(text) {
+        read_pint(text)
+    } else {
+        read_gnu(text)
+    };
+    let (defs, mut skipped) = file.translate(registry);
+    let (units, errors) = registry.import(defs, source);
+    skipped.extend(errors);
+    skipped.sort_by_key(|&(line, _)| line);
+    (units, skipped)
+}
+
+// pint writes `name = definition`, GNU units `name definition`
+fn is_pint(text: &str) -> bool {
+    text.lines()
+        .map(|line| line.split('#').next().unwrap().trim())
+        .find(|line| !line.is_empty())
+        .is_some_and(|line| line.starts_with('@') || line.contains('='))
+}
+
+struct ForeignUnit {
+    line: usize,
+    symbol: String,
+    expr: String,
+    names: Vec<String>,
+    zero: Option<String>,
+}
+
+/// A foreign file read line by line, before its expressions are rewritten
+/// for the registry.
+#[derive(Default)]
+struct ForeignFile {
+    units: Vec<ForeignUnit>,
+    /// (line, name, expression)
+    prefixes: Vec<(usize, String, String)>,
+    skipped: Vec<(usize, String)>,
+}
+
+impl ForeignFile {
+    fn skip(&mut self, line: usize, reason: impl Into<String>) {
+        self.skipped.push((line, reason.into()));
+    }
+
+    fn translate(mut self, registry: &Registry) -> (Definitions, Vec<(usize, String)>) {
+        let mut translator = Translator {
+            registry,
+            names: HashMap::new(),
+            prefixes: PREFIXES
+                .iter()
+                .flat_map(|p| {
+                    [
+                        (p.name.to_string(), p.factor),
+                        (p.symbol.to_string(), p.factor),
+                    ]
+                })
+                .collect(),
+        };
+        // prefixes in file order, as they may refer to each other
+        for (line, name, expr) in &self.prefixes {
+            let expr = translator.expr(expr);
+            let value = defs::eval(&expr, *line, &mut |n| match translator.prefix(n) {
+                Some(factor) => Ok(Value::number(factor)),
+                None => Err(defs::DefError::new(
+                    *line,
+                    defs::DefErrorKind::UnknownPrefix(n.into()),
+                )),
+            });
+            match value {
+                Ok(value) if value.dim == crate::units::Dim::zero() => {
+                    translator.prefixes.insert(0, (name.clone(), value.factor));
+                }
+                Ok(_) => self
+                    .skipped
+                    .push((*line, format!("prefix {name}- is not a number"))),
+                Err(e) => self.skipped.push((*line, e.kind.to_string())),
+            }
+        }
+        let mut units = Vec::new();
+        for unit in &self.units {
+            if !is_valid_symbol(&unit.symbol) {
+                self.skipped.push((
+                    unit.line,
+                    format!("{} is not a valid unit name", unit.symbol),
+                ));
+                continue;
+            }
+            for name in std::iter::once(&unit.symbol).chain(&unit.names) {
+                translator.names.insert(name.clone(), unit.symbol.clone());
+            }
+            units.push(unit);
+        }
+        let units = units
+            .into_iter()
+            .map(|unit| UnitDecl {
+                line: unit.line,
+                symbol: unit.symbol.clone(),
+                expr: translator.expr(&unit.expr),
+                group: 0,
+                prefixes: Vec::new(),
+                names: unit.names.clone(),
+                desc: None,
+                zero: unit.zero.clone(),
+                local: Vec::new(),
+                force: false,
+            })
+            .collect();
+        let defs = Definitions {
+            groups: vec![GroupDecl {
+                line: 0,
+                name: String::from("import"),
+                dim: None,
+                kind: None,
+                system: None,
+                short: None,
+            }],
+            units,
+            ..Definitions::default()
+        };
+        (defs, self.skipped)
+    }
+}
+
+// rewrites foreign expressions in the syntax of the definitions format
+struct Translator<'a> {
+    registry: &'a Registry,
+    /// names of the file's units, to their symbols
+    names: HashMap<String, String>,
+    /// the file's prefixes first, then the SI ones
+    prefixes: Vec<(String, f64)>,
+}
+
+impl Translator<'_> {
+    fn prefix(&self, name: &str) -> Option<f64> {
+        self.prefixes
+            .iter()
+            .find(|(p, _)| p == name)
+            .map(|&(_, f)| f)
+    }
+
+    fn unit(&self, name: &str) -> Option<String> {
+        if let Some(symbol) = self.names.get(name) {
+            return Some(symbol.clone());
+        }
+        self.registry.lookup(name).map(|unit| unit.symbol.clone())
+    }
+
+    // a name as the registry knows it: a unit, a plural, a prefix of its
+    // own, a prefixed unit or, as in GNU units, a unit with its exponent
+    // appended (`cm3`)
+    fn name(&self, name: &str) -> Option<String> {
+        if let Some(symbol) = self.unit(name) {
+            return Some(symbol);
+        }
+        if let Some(factor) = self.prefix(name) {
+            return Some(format!("{factor:e}"));
+        }
+        if let Some(symbol) = name.strip_suffix('s').and_then(|n| self.unit(n)) {
+            return Some(symbol);
+        }
+        for (prefix, factor) in &self.prefixes {
+            if let Some(rest) = name.strip_prefix(prefix.as_str())
+                && let Some(symbol) = self.unit(rest)
+            {
+                return Some(format!("({factor:e} {symbol})"));
+            }
+        }
+        let base = name.trim_end_matches(|c: char| c.is_ascii_digit());
+        if !base.is_empty() && base.len() < name.len() {
+            return self
+                .name(base)
+                .map(|symbol| format!("{symbol}^{}", &name[base.len()..]));
+        }
+        None
+    }
+
+    fn expr(&self, expr: &str) -> String {
+        let mut out = String::new();
+        let mut chars = expr.chars().peekable();
+        while let Some(&c) = chars.peek() {
+            if c.is_alphabetic() || c == '_' {
+                let mut name = String::new();
+                while let Some(&c) = chars.peek()
+                    && (c.is_alphanumeric() || c == '_')
+                {
+                    name.push(c);
+                    chars.next();
+                }
+                // unknown names are kept for the registry to report
+                out.push_str(&self.name(&name).unwrap_or(name));
+            } else if c.is_ascii_digit() || c == '.' {
+                let mut number = String::new();
+                while let Some(&c) = chars.peek()
+                    && (c.is_ascii_alphanumeric()
+                        || c == '.'
+                        || c == '|'
+                        || ((c == '-' || c == '+') && number.ends_with(['e', 'E'])))
+                {
+                    number.push(c);
+                    chars.next();
+                }
+                // GNU units writes exact fractions as `1|3`
+                match number.split_once('|') {
+                    Some((a, b)) => out.push_str(&format!("({a}/{b})")),
+                    None => out.push_str(&number),
+                }
+            } else if c == '*' {
+                chars.next();
+                if chars.peek() == Some(&'*') {
+                    chars.next();
+                    out.push('^');
+                } else {
+                    out.push('*');
+                }
+            } else {
+                out.push(c);
+                chars.next();
+            }
+        }
+        out
+    }
+}
+
+// GNU units: `name definition`, prefixes end in `-`, `!` marks base
+// units and directives, `\` continues a line
+fn read_gnu(text: &str) -> ForeignFile {
+    let mut file = ForeignFile::default();
+    let mut lines = text.lines().enumerate();
+    while let Some((i, raw)) = lines.next() {
+        let line = i + 1;
+        let mut content = raw.split('#').next().unwrap().trim_end().to_string();
+        while content.ends_with('\\')
+            && let Some((_, next)) = lines.next()
+        {
+            content.pop();
+            content.push(' ');
+            content.push_str(next.split('#').next().unwrap().trim());
+        }
+        let content = content.trim();
+        if content.is_empty() {
+            continue;
+        }
+        if content.starts_with('!') {
+            let directive = content.split_whitespace().next().unwrap();
+            file.skip(line, format!("directive {directive} is not supported"));
+            continue;
+        }
+        let (name, def) = match content.split_once(char::is_whitespace) {
+            Some((name, def)) => (name, def.trim()),
+            None => (content, ""),
+        };
+        if name.contains('(') {
+            file.skip(line, format!("nonlinear unit {name} is not supported"));
+        } else if name.contains('[') {
+            file.skip(line, format!("table {name} is not supported"));
+        } else if def.is_empty() {
+            file.skip(line, format!("{name} has no definition"));
+        } else if def.starts_with('!') {
+            file.skip(line, format!("base unit {name} is not supported"));
+        } else if let Some(prefix) = name.strip_suffix('-') {
+            file.prefixes
+                .push((line, prefix.to_string(), def.to_string()));
+        } else {
+            file.units.push(ForeignUnit {
+                line,
+                symbol: name.to_string(),
+                expr: def.to_string(),
+                names: Vec::new(),
+                zero: None,
+            });
+        }
+    }
+    file
+}
+
+// pint: `name = definition [= symbol] [= alias ...]`, prefixes end in `-`,
+// `[dimension]` definitions and `@` blocks
+fn read_pint(text: &str) -> ForeignFile {
+    let mut file = ForeignFile::default();
+    let mut skipping_block = false;
+    for (i, raw) in text.lines().enumerate() {
+        let line = i + 1;
+        let content = raw.split('#').next().unwrap().trim();
+        if content.is_empty() {
+            continue;
+        }
+        if let Some(directive) = content.strip_prefix('@') {
+            let keyword = directive.split_whitespace().next().unwrap_or("");
+            match keyword {
+                "end" => skipping_block = false,
+                // the units of a group are ordinary definitions
+                "group" => {}
+                "system" | "context" | "defaults" => {
+                    skipping_block = true;
+                    file.skip(line, format!("@{keyword} blocks are not supported"));
+                }
+                _ => file.skip(line, format!("@{keyword} is not supported")),
+            }
+            continue;
+        }
+        if skipping_block {
+            continue;
+        }
+        let parts: Vec<&str> = content.split('=').map(str::trim).collect();
+        let [name, def, aliases @ ..] = parts.as_slice() else {
+            file.skip(line, "expected `<name> = <definition>`");
+            continue;
+        };
+        if name.starts_with('[') {
+            // derived dimensions follow from the units themselves
+            continue;
+        }
+        if def.starts_with('[') {
+            file.skip(line, format!("base unit {name} is not supported"));
+            continue;
+        }
+        if let Some(prefix) = name.strip_suffix('-') {
+            file.prefixes
+                .push((line, prefix.to_string(), def.to_string()));
+            for alias in aliases.iter().filter_map(|a| a.strip_suffix('-')) {
+                if !alias.is_empty() && alias != "_" {
+                    file.prefixes
+                        .push((line, alias.to_string(), def.to_string()));
+                }
+            }
+            continue;
+        }
+        let mut modifiers = def.split(';').map(str::trim);
+        let expr = modifiers.next().unwrap();
+        let mut zero = None;
+        let mut nonlinear = None;
+        for modifier in modifiers {
+            match modifier.split_once(':').map(|(k, v)| (k.trim(), v.trim())) {
+                Some(("offset", value)) => zero = Some(value.to_string()),
+                _ => nonlinear = nonlinear.or(Some(modifier)),
+            }
+        }
+        if let Some(modifier) = nonlinear {
+            file.skip(
+                line,
+                format!("nonlinear unit {name} ({modifier}) is not supported"),
+            );
+            continue;
+        }
+        // the symbol, when there is one, is what the registry knows it by
+        let (symbol, names) = match aliases {
+            [symbol, rest @ ..] if *symbol != "_" && is_valid_symbol(symbol) => (
+                symbol.to_string(),
+                std::iter::once(name).chain(rest).collect::<Vec<_>>(),
+            ),
+            [_, rest @ ..] => (name.to_string(), rest.iter().collect()),
+            [] => (name.to_string(), Vec::new()),
+        };
+        file.units.push(ForeignUnit {
+            line,
+            symbol,
+            expr: expr.to_string(),
+            names: names
+                .into_iter()
+                .filter(|n| is_valid_symbol(n))
+                .map(|n| n.to_string())
+                .collect(),
+            zero,
+        });
+    }
+    file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::CODATA_2022;
+
+    fn factor(registry: &Registry, name: &str) -> f64 {
+        registry.lookup(name).unwrap().def.si_factor(&CODATA_2022)
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        ((a - b) / b).abs() < 1e-12
+    }
+
+    #[test]
+    fn gnu_units() {
+        let text = "\
+# team units
+furlong         1|8 mile
+fortnights_per  1 / fortnight
+smoot           67 in
+megasmoot       1e6 smoot
+ksmoot          k\\
+                smoot
+micro-          1e-6
+brick-          1e4 micro
+bricksmoot      1 bricksmoot_extra
+tempX(x)        units=[1;K] x+1
+!include more.units
+wheel           !
+gallon3         gallon^3
+area            cm2 furlong
+";
+        let mut registry = Registry::builtin();
+        let (units, skipped) = import_text(&mut registry, text, Source::User);
+        assert_eq!(units, 7);
+        assert!(close(factor(&registry, "furlong"), 201.168));
+        assert!(close(factor(&registry, "smoot"), 1.7018));
+        assert!(close(factor(&registry, "megasmoot"), 1.7018e6));
+        assert!(close(factor(&registry, "ksmoot"), 1701.8));
+        assert!(close(factor(&registry, "area"), 201.168e-4));
+        let lines: Vec<usize> = skipped.iter().map(|&(line, _)| line).collect();
+        assert_eq!(lines, [10, 11, 12, 13]);
+        assert!(skipped[1].1.contains("nonlinear"));
+    }
+
+    #[test]
+    fn forward_references() {
+        let text = "\
+fwda    2 fwdb
+fwdb    3 fwdc
+fwdc    1 m
+fwdd    2 fwde
+fwde    1 nosuch
+fwdf    2 fwdd
+fwdg    fwdg
+";
+        let mut registry = Registry::builtin();
+        let (units, skipped) = import_text(&mut registry, text, Source::User);
+        assert_eq!(units, 3);
+        assert!(close(factor(&registry, "fwda"), 6.0));
+        let skipped: Vec<(usize, &str)> =
+            skipped.iter().map(|(line, reason)| (*line, reason.as_str())).collect();
+        assert_eq!(skipped[..3], [
+            (4, "unknown unit fwde"),
+            (5, "unknown unit nosuch"),
+            (6, "unknown unit fwdd"),
+        ]);
+        assert_eq!(skipped[3].0, 7);
+    }
+
+    #[test]
+    fn pint_definitions() {
+        let text = "\
+# pint style
+kilo- = 1e3 = k-
+meter = [length] = m = metre
+@group Team
+    furlong = 201.168 * meter = fur = furlongs
+    league = 3 * mile = _ = leagues
+@end
+@context sp
+    [length] -> [energy]: planck_constant * speed_of_light / value
+@end
+[speed] = [length] / [time]
+degR2 = 5 / 9 * kelvin; offset: 0 = R2
+decibel2 = 1 ; logbase: 10; logfactor: 10 = dB2
+area2 = fur ** 2
+";
+        let mut registry = Registry::builtin();
+        let (units, skipped) = import_text(&mut registry, text, Source::User);
+        assert_eq!(units, 4);
+        assert!(close(factor(&registry, "fur"), 201.168));
+        assert!(close(factor(&registry, "furlongs"), 201.168));
+        assert!(close(factor(&registry, "leagues"), 3.0 * 1609.344));
+        assert!(close(factor(&registry, "area2"), 201.168 * 201.168));
+        assert!(close(factor(&registry, "R2"), 5.0 / 9.0));
+        let lines: Vec<usize> = skipped.iter().map(|&(line, _)| line).collect();
+        assert_eq!(lines, [3, 8, 13]);
+    }
+}
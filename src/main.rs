@@ -1,8 +1,23 @@
+mod check;
+mod chemistry;
 mod cli;
 mod constants;
 mod convert;
+mod currency;
+mod defs;
+mod duration;
+mod equivalence;
+mod explain;
+mod import;
 mod parse;
+mod rational;
+mod registry;
+mod render;
 mod repl;
+mod settings;
+mod simplify;
+mod substances;
+mod systems;
 mod units;
 
 use clap::Parser;
@@ -13,8 +28,30 @@ use crate::repl::run_repl;
 
 fn main() {
     let cli = Cli::parse();
+    if let Err(e) = cli.apply_settings() {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+    if let Err(e) = cli.load_rates() {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+    // a bad line in the user's file must not take the built-in units down
+    if let Err(e) = registry::load_user() {
+        eprintln!("warning: {e}, going on without the user's units");
+    }
+    if let Err(e) = cli.import_defs() {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+    if cli.check_db {
+        if !check::run() {
+            std::process::exit(1);
+        }
+        return;
+    }
     if let Some(expr) = cli.expr {
-        run_once(expr.as_str())
+        run_once(expr.as_str(), cli.json)
     } else {
         if let Err(e) = run_repl() {
             eprintln!("Error: {}", e);
@@ -1,7 +1,25 @@
 use thiserror::Error;
 
-use crate::constants::UNIT_DEF_MAP;
-use crate::units::Dim;
+use crate::constants::Codata;
+use crate::currency;
+use crate::duration::{self, DurationStyle};
+use crate::equivalence::{self, Bridge};
+use crate::rational::Ratio;
+use crate::registry::{self, Resolved};
+use crate::settings::settings;
+use crate::simplify;
+use crate::substances;
+use crate::systems::UnitSystem;
+use crate::units::{Dim, Exact, QuantityKind, UnitDef};
+
+/// Spellings of the `to` keyword.
+pub const TO_KEYWORDS: &[&str] = &["to", "转换为", "换算成"];
+
+/// CJK ideographs, which start identifiers of their own: Chinese is written
+/// without spaces, so `10公里转换为米` splits at every change of script.
+fn is_cjk(c: char) -> bool {
+    matches!(c, '\u{4e00}'..='\u{9fff}' | '\u{3400}'..='\u{4dbf}' | '\u{f900}'..='\u{faff}')
+}
 
 #[derive(Debug, Error)]
 pub enum ParseErrorKind<'a> {
@@ -14,6 +32,12 @@ pub enum ParseErrorKind<'a> {
     #[error("Invalid exponent")]
     InvalidExponent,
 
+    #[error("Invalid ISO-8601 duration")]
+    InvalidDuration,
+
+    #[error("Only time can be shown as a duration, got {0}")]
+    NotDuration(Dim),
+
     #[error("Missing 'to'")]
     MissingTo,
 
@@ -23,15 +47,48 @@ pub enum ParseErrorKind<'a> {
     #[error("Unknown unit: {0}")]
     UnknownUnit(&'a str),
 
+    #[error("Ambiguous unit {0}, did you mean {1}?")]
+    AmbiguousUnit(&'a str, String),
+
+    #[error("Unknown currency {0}, no exchange rates loaded (use --rates <file>)")]
+    NoRates(&'a str),
+
+    #[error("Unknown currency {0}, the exchange rates did not load: {1}")]
+    RatesNotLoaded(&'a str, String),
+
+    #[error("Unknown substance: {0}")]
+    UnknownSubstance(&'a str),
+
     #[error("Incompatible dimension from:{0} to:{1}")]
     IncompatibleDim(Dim, Dim),
 
+    #[error("Incompatible quantity kind from:{0} to:{1}")]
+    IncompatibleKind(QuantityKind, QuantityKind),
+
     #[error("Au to Au not supported")]
     AuToAu,
 
     #[error("Au must be single")]
     AuMustSingle,
 
+    #[error("Au to {0} units not supported")]
+    AuToSystem(UnitSystem),
+
+    #[error("Au to auto not supported, give a unit")]
+    AuToAuto,
+
+    #[error("Au to simplest not supported, give a unit")]
+    AuToSimplest,
+
+    #[error("Dimension {0} has no {1} units")]
+    NoSystemMapping(Dim, UnitSystem),
+
+    #[error("Cannot mix {0} and {1} electromagnetic units in one expression")]
+    MixedSystems(UnitSystem, UnitSystem),
+
+    #[error("{0} and {1} electromagnetic units only correspond under `:set system gaussian`")]
+    SystemMismatch(UnitSystem, UnitSystem),
+
     #[error("Unexpected Number")]
     UnexpectedNumber,
 
@@ -46,17 +103,72 @@ pub struct ParseError<'a> {
     pub kind: ParseErrorKind<'a>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct UnitExpr {
     pub symbol: String,
     pub dim: Dim,
     pub factor: f64,
+    pub system: Option<UnitSystem>,
+    pub kind: Option<QuantityKind>,
+    /// SI value of the zero of a temperature scale used on its own
+    pub offset: f64,
+    /// `factor` and `offset` as fractions, when all the units are exact
+    pub exact: Option<Exact>,
+    /// the units the expression is made of, for `explain`
+    pub atoms: Vec<Atom>,
 }
 
-#[derive(Debug)]
+/// One unit of a unit expression with its power, e.g. `day^-1` in `kWh/day`.
+#[derive(Debug, Clone)]
+pub struct Atom {
+    pub symbol: String,
+    pub power: i32,
+    /// SI factor and dimension of the unit itself, before the power
+    pub factor: f64,
+    pub dim: Dim,
+    /// the factor depends on the CODATA release
+    pub measured: bool,
+}
+
+impl Atom {
+    pub fn new(symbol: &str, def: &UnitDef, codata: &Codata) -> Atom {
+        Atom {
+            symbol: symbol.to_string(),
+            power: 1,
+            factor: def.si_factor(codata),
+            dim: def.dim,
+            measured: def.scale.is_some(),
+        }
+    }
+}
+
+impl UnitExpr {
+    fn merge_system(&mut self, other: &UnitExpr) -> Result<(), ParseErrorKind<'static>> {
+        match (self.system, other.system) {
+            (Some(a), Some(b)) if a != b => Err(ParseErrorKind::MixedSystems(a, b)),
+            (None, b) => {
+                self.system = b;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum UnitTarget {
     Au,
     Unit(UnitExpr),
+    /// `to natural` etc.: the base units of a whole system, only valid as a target
+    System(UnitSystem),
+    /// `to duration` / `to iso8601`, only valid as a target
+    Duration(DurationStyle),
+    /// `to auto`, or no target: a unit of the source's group that keeps the
+    /// value in [1, 1000), only valid as a target
+    Auto,
+    /// `to simplest`: the shortest expression in named units, see
+    /// `simplify::simplest`; replaced by that unit in `parse_expr`
+    Simplest,
 }
 
 impl<'a> ParseError<'a> {
@@ -69,7 +181,7 @@ impl std::fmt::Display for ParseError<'_> {
         let line = self.line.trim_end_matches(['\n', '\r']);
         writeln!(f, "{line}")?;
         if let Some(pos) = self.pos {
-            writeln!(f, "{}^---{}", " ".repeat(pos), self.kind)?;
+            writeln!(f, "{}^---{}", " ".repeat(self.column(pos)), self.kind)?;
         } else {
             writeln!(f, "{}", self.kind)?;
         }
@@ -77,9 +189,17 @@ impl std::fmt::Display for ParseError<'_> {
     }
 }
 impl ParseError<'_> {
+    // terminal column of byte offset `pos`, CJK characters are two wide
+    fn column(&self, pos: usize) -> usize {
+        self.line[..pos]
+            .chars()
+            .map(|c| if is_cjk(c) { 2 } else { 1 })
+            .sum()
+    }
+
     pub fn format_repl(&self) -> String {
         if let Some(pos) = self.pos {
-            format!("      {}^---{}", " ".repeat(pos), self.kind)
+            format!("      {}^---{}", " ".repeat(self.column(pos)), self.kind)
         } else {
             format!("{}", self.kind)
         }
@@ -89,10 +209,16 @@ impl ParseError<'_> {
 struct Lexer<'a> {
     s: &'a str,
     pos: usize,
+    // an unqualified `au` was read
+    bare_au: bool,
 }
 impl<'a> Lexer<'a> {
     fn new(s: &'a str) -> Self {
-        Self { s, pos: 0 }
+        Self {
+            s,
+            pos: 0,
+            bare_au: false,
+        }
     }
     fn err<T>(&self, kind: ParseErrorKind<'a>) -> Result<T, ParseError<'a>> {
         Err(ParseError::new(self.s, Some(self.pos), kind))
@@ -152,12 +278,14 @@ impl<'a> Lexer<'a> {
             }
             if let Some(c) = self.peek()
                 && !c.is_ascii_whitespace()
+                && !is_cjk(c)
             {
                 return self.err(ParseErrorKind::InvalidExponent);
             }
         }
         if let Some(c) = self.peek()
             && !c.is_ascii_whitespace()
+            && !is_cjk(c)
         {
             return self.err(ParseErrorKind::InvalidNumber);
         }
@@ -167,6 +295,44 @@ impl<'a> Lexer<'a> {
             Err(_) => self.err(ParseErrorKind::InvalidNumber),
         }
     }
+    fn at_duration(&mut self) -> bool {
+        self.skip_ws();
+        let rest = &self.s[self.pos..];
+        rest.starts_with('P') || rest.starts_with("-P")
+    }
+    // ISO-8601 duration literal, in seconds
+    fn parse_duration(&mut self) -> Result<(f64, UnitTarget), ParseError<'a>> {
+        self.skip_ws();
+        let start = self.pos;
+        while matches!(self.peek(), Some(c) if !c.is_whitespace()) {
+            self.bump();
+        }
+        match duration::parse_iso8601(&self.s[start..self.pos]) {
+            Some(v) => Ok((
+                v,
+                UnitTarget::Unit(UnitExpr {
+                    symbol: String::from("s"),
+                    dim: Dim::time(1),
+                    factor: 1.0,
+                    system: None,
+                    kind: Some(QuantityKind::Time),
+                    offset: 0.0,
+                    exact: None,
+                    atoms: vec![Atom {
+                        symbol: String::from("s"),
+                        power: 1,
+                        factor: 1.0,
+                        dim: Dim::time(1),
+                        measured: false,
+                    }],
+                }),
+            )),
+            None => {
+                self.pos = start;
+                self.err(ParseErrorKind::InvalidDuration)
+            }
+        }
+    }
     fn parse_unit_expr(&mut self) -> Result<UnitTarget, ParseError<'a>> {
         self.skip_ws();
         let mut lhs = self.parse_unit_term()?;
@@ -181,7 +347,15 @@ impl<'a> Lexer<'a> {
                     if let UnitTarget::Unit(l_expr) = &mut lhs
                         && let UnitTarget::Unit(r_expr) = &rhs
                     {
+                        if let Err(kind) = l_expr.merge_system(r_expr) {
+                            self.pos = start;
+                            return self.err(kind);
+                        }
                         l_expr.dim.mul(r_expr.dim);
+                        l_expr.offset = 0.0;
+                        l_expr.exact = compose(l_expr.exact, r_expr.exact, Ratio::mul);
+                        l_expr.atoms.extend(r_expr.atoms.iter().cloned());
+                        l_expr.kind = QuantityKind::mul(l_expr.kind, r_expr.kind);
                         l_expr.factor *= r_expr.factor;
                         l_expr
                             .symbol
@@ -197,7 +371,19 @@ impl<'a> Lexer<'a> {
                     if let UnitTarget::Unit(l_expr) = &mut lhs
                         && let UnitTarget::Unit(r_expr) = &rhs
                     {
+                        if let Err(kind) = l_expr.merge_system(r_expr) {
+                            self.pos = start;
+                            return self.err(kind);
+                        }
                         l_expr.dim.div(r_expr.dim);
+                        l_expr.offset = 0.0;
+                        l_expr.exact = compose(l_expr.exact, r_expr.exact, Ratio::div);
+                        l_expr.atoms.extend(r_expr.atoms.iter().map(|atom| Atom {
+                            power: -atom.power,
+                            ..atom.clone()
+                        }));
+                        l_expr.kind =
+                            QuantityKind::mul(l_expr.kind, QuantityKind::pow(r_expr.kind, -1));
                         l_expr.factor /= r_expr.factor;
                         l_expr
                             .symbol
@@ -214,14 +400,44 @@ impl<'a> Lexer<'a> {
     }
     fn parse_unit_term(&mut self) -> Result<UnitTarget, ParseError<'a>> {
         self.skip_ws();
-        let mut atom = self.parse_unit_atom()?;
+        let mut atom = if self.peek() == Some('(') {
+            self.bump();
+            let inner = self.parse_unit_expr()?;
+            self.skip_ws();
+            if self.peek() != Some(')') {
+                return self.err(ParseErrorKind::BadSyntax("expect ')'"));
+            }
+            self.bump();
+            match inner {
+                UnitTarget::Unit(mut expr) => {
+                    expr.symbol = format!("({})", expr.symbol);
+                    UnitTarget::Unit(expr)
+                }
+                inner => inner,
+            }
+        } else {
+            self.parse_unit_atom()?
+        };
         self.skip_ws();
         if matches!(self.peek(), Some('^')) {
             self.bump();
             let k = self.parse_signed_int()?;
             if let UnitTarget::Unit(expr) = &mut atom {
                 expr.dim.pow(k);
-                expr.factor = expr.factor.powi(k as i32);
+                expr.kind = QuantityKind::pow(expr.kind, k);
+                expr.factor = expr.factor.powi(k);
+                for atom in &mut expr.atoms {
+                    atom.power *= k;
+                }
+                if k != 1 {
+                    expr.offset = 0.0;
+                    expr.exact = expr.exact.and_then(|exact| {
+                        Some(Exact {
+                            factor: exact.factor.pow(k)?,
+                            offset: Ratio::ZERO,
+                        })
+                    });
+                }
                 expr.symbol.push_str(format!("^{}", k).as_str());
             } else {
                 return self.err(ParseErrorKind::AuMustSingle);
@@ -232,26 +448,115 @@ impl<'a> Lexer<'a> {
     fn parse_unit_atom(&mut self) -> Result<UnitTarget, ParseError<'a>> {
         self.skip_ws();
         let start = self.pos;
-        if matches!(self.peek(), Some(c) if c.is_ascii_digit() || c=='+' || c== '-') {
+        // a lone `1` is the dimensionless unit, as in `1/s` or `to 1`
+        let one = self.peek() == Some('1')
+            && !matches!(self.s[start + 1..].chars().next(), Some(c) if c.is_alphanumeric() || c == '.');
+        if !one && matches!(self.peek(), Some(c) if c.is_ascii_digit() || c=='+' || c== '-') {
             return self.err(ParseErrorKind::UnexpectedNumber);
         }
-        let name = self.read_ident_token()?;
+        let name = if one {
+            self.bump();
+            &self.s[start..self.pos]
+        } else {
+            self.read_ident_token()?
+        };
+        // longest match, for names of two words such as `degrees Celsius`
+        let mut lookup = String::from(name);
+        let first_end = self.pos;
+        if let Ok(second) = self.read_ident_token()
+            && registry::is_unit(format!("{name} {second}").as_str())
+        {
+            lookup = format!("{name} {second}");
+        } else {
+            self.pos = first_end;
+        }
         let unit_str = &self.s[start..self.pos];
         if unit_str == "au" {
-            Ok(UnitTarget::Au)
-        } else if let Some(e) = UNIT_DEF_MAP.get(name) {
+            self.bare_au = true;
+            return Ok(UnitTarget::Au);
+        }
+        if unit_str == "atomic:au" {
+            return Ok(UnitTarget::Au);
+        }
+        // a loaded currency code goes before names matched ignoring case,
+        // so that `CUP` stays the Cuban peso
+        let currency =
+            currency::unit(name).filter(|_| registry::with(|r| r.lookup(&lookup).is_none()));
+        let def = match name.split_once(':') {
+            Some((namespace, local)) => registry::qualified_unit(namespace, local),
+            None if currency.is_some() => currency,
+            None => match registry::resolve(&lookup) {
+                Resolved::Unit(symbol) => registry::unit_def(&symbol),
+                Resolved::Ambiguous(symbols) => {
+                    self.pos = start;
+                    return self.err(ParseErrorKind::AmbiguousUnit(
+                        unit_str,
+                        symbols.join(" or "),
+                    ));
+                }
+                Resolved::Unknown => None,
+            },
+        };
+        if let Some(e) = def {
+            let codata = settings().codata;
             Ok(UnitTarget::Unit(UnitExpr {
                 symbol: String::from(unit_str),
                 dim: e.dim,
-                factor: e.factor,
+                factor: e.si_factor(codata),
+                system: e.system,
+                kind: e.kind,
+                offset: e.offset,
+                exact: e.exact,
+                atoms: vec![Atom::new(unit_str, &e, codata)],
             }))
         } else {
             let ident_str = &self.s[start..self.pos];
             self.pos = start;
+            if currency::rates().is_none()
+                && ident_str.len() == 3
+                && ident_str.chars().all(|c| c.is_ascii_uppercase())
+            {
+                return self.err(match currency::default_error() {
+                    Some(e) => ParseErrorKind::RatesNotLoaded(ident_str, e),
+                    None => ParseErrorKind::NoRates(ident_str),
+                });
+            }
             self.err(ParseErrorKind::UnknownUnit(ident_str))
         }
     }
-    // 字母开头，后面可以接数字或者点
+    fn parse_target(&mut self) -> Result<UnitTarget, ParseError<'a>> {
+        self.skip_ws();
+        let start = self.pos;
+        if let Ok(ident) = self.read_ident_token()
+            && !registry::is_unit(ident)
+            && let Some(system) = UnitSystem::from_name(ident)
+        {
+            return Ok(UnitTarget::System(system));
+        }
+        self.pos = start;
+        if let Ok(ident) = self.read_ident_token()
+            && !registry::is_unit(ident)
+            && let Some(style) = DurationStyle::from_name(ident)
+        {
+            return Ok(UnitTarget::Duration(style));
+        }
+        self.pos = start;
+        if let Ok("auto") = self.read_ident_token()
+            && !registry::is_unit("auto")
+        {
+            return Ok(UnitTarget::Auto);
+        }
+        self.pos = start;
+        if let Ok("simplest") = self.read_ident_token()
+            && !registry::is_unit("simplest")
+        {
+            return Ok(UnitTarget::Simplest);
+        }
+        self.pos = start;
+        self.parse_unit_expr()
+    }
+    // 字母开头，后面可以接数字、点、下划线，`:` 用于限定命名空间；`%` 单独成词；
+    // 汉字连续成词，遇到 `转换为`/`换算成` 断开
     fn read_ident_token(&mut self) -> Result<&'a str, ParseError<'a>> {
         self.skip_ws();
         let start = self.pos;
@@ -261,16 +566,41 @@ impl<'a> Lexer<'a> {
             line: self.s,
             pos: Some(self.pos),
         })?;
+        if first == '%' {
+            self.bump();
+            return Ok(&self.s[start..self.pos]);
+        }
+        if is_cjk(first) {
+            let at_keyword = |pos: usize| {
+                TO_KEYWORDS
+                    .iter()
+                    .find(|kw| self.s[pos..].starts_with(**kw))
+            };
+            if let Some(kw) = at_keyword(start) {
+                self.pos += kw.len();
+                return Ok(&self.s[start..self.pos]);
+            }
+            let mut end = start;
+            for c in self.s[start..].chars() {
+                if !is_cjk(c) || (end > start && at_keyword(end).is_some()) {
+                    break;
+                }
+                end += c.len_utf8();
+            }
+            self.pos = end;
+            return Ok(&self.s[start..self.pos]);
+        }
         if !first.is_ascii_alphabetic() {
             return self.err(ParseErrorKind::UnexpectedChar(first));
         }
-        while matches!(self.peek(), Some(c) if c.is_alphanumeric() || c=='.') {
+        while matches!(self.peek(), Some(c) if (c.is_alphanumeric() && !is_cjk(c)) || matches!(c, '.' | '_' | ':'))
+        {
             self.bump();
         }
 
         Ok(&self.s[start..self.pos])
     }
-    fn parse_signed_int(&mut self) -> Result<i64, ParseError<'a>> {
+    fn parse_signed_int(&mut self) -> Result<i32, ParseError<'a>> {
         self.skip_ws();
         let start = self.pos;
         if matches!(self.peek(), Some('+' | '-')) {
@@ -286,16 +616,59 @@ impl<'a> Lexer<'a> {
             return self.err(ParseErrorKind::InvalidNumber);
         }
         let num_str = &self.s[start..self.pos];
-        match num_str.parse::<i64>() {
+        match num_str.parse::<i32>() {
             Ok(v) => Ok(v),
             Err(_) => self.err(ParseErrorKind::InvalidExponent),
         }
     }
+    // a substance name or a chemical formula such as `Ca(OH)2` or `CuSO4·5H2O`
+    fn read_substance_token(&mut self) -> Result<&'a str, ParseError<'a>> {
+        self.skip_ws();
+        let start = self.pos;
+        match self.peek() {
+            Some(c) if c.is_ascii_alphabetic() || c == '(' => {}
+            Some(c) => return self.err(ParseErrorKind::UnexpectedChar(c)),
+            None => return self.err(ParseErrorKind::BadSyntax("expect substance")),
+        }
+        while matches!(self.peek(), Some(c) if (c.is_alphanumeric() && !is_cjk(c)) || "()·._*".contains(c))
+        {
+            self.bump();
+        }
+        Ok(&self.s[start..self.pos])
+    }
+    // `of <substance>`, `[<substance>]` or a bare known substance after the
+    // source unit
+    fn parse_qualifier(&mut self) -> Result<Option<&'a str>, ParseError<'a>> {
+        self.skip_ws();
+        let start = self.pos;
+        if self.peek() == Some('[') {
+            self.bump();
+            let name = self.read_substance_token()?;
+            self.skip_ws();
+            if self.bump() != Some(']') {
+                return self.err(ParseErrorKind::BadSyntax("expect ']'"));
+            }
+            return Ok(Some(name));
+        }
+        if let Ok("of") = self.read_ident_token() {
+            return Ok(Some(self.read_substance_token()?));
+        }
+        self.pos = start;
+        // only a word that names a substance, so that `1 km m` still
+        // reports the missing `to`
+        if let Ok(name) = self.read_substance_token()
+            && substances::is_known(name)
+        {
+            return Ok(Some(name));
+        }
+        self.pos = start;
+        Ok(None)
+    }
     fn consume_kw_to(&mut self) -> Result<(), ParseError<'a>> {
         self.skip_ws();
         let start = self.pos;
         let ident = self.read_ident_token()?;
-        if ident == "to" {
+        if TO_KEYWORDS.contains(&ident) {
             Ok(())
         } else {
             self.pos = start;
@@ -304,7 +677,7 @@ impl<'a> Lexer<'a> {
     }
     fn ensure_eof(&mut self) -> Result<(), ParseError<'a>> {
         self.skip_ws();
-        if self.peek() == None {
+        if self.peek().is_none() {
             Ok(())
         } else {
             self.err(ParseErrorKind::BadSyntax("trailing input"))
@@ -312,45 +685,204 @@ impl<'a> Lexer<'a> {
     }
 }
 
+// the factor of `a*b` or `a/b`, exact while both sides are
+fn compose(
+    a: Option<Exact>,
+    b: Option<Exact>,
+    op: fn(Ratio, Ratio) -> Option<Ratio>,
+) -> Option<Exact> {
+    let (a, b) = a.zip(b)?;
+    Some(Exact {
+        factor: op(a.factor, b.factor)?,
+        offset: Ratio::ZERO,
+    })
+}
+
 #[derive(Debug)]
 pub struct ConversionExpr {
     pub value: f64,
+    /// `value` as written, e.g. 1/10 for `0.1`; `None` for durations
+    pub exact_value: Option<Ratio>,
     pub from: UnitTarget,
     pub to: UnitTarget,
+    /// set when the dimensions differ and an equivalence links them
+    pub bridge: Option<Bridge>,
+    /// data the result depends on, e.g. the date of the exchange rates
+    pub notes: Vec<String>,
+    pub warnings: Vec<String>,
 }
 
-pub fn parse_expr(line: &str) -> Result<ConversionExpr, ParseError<'_>> {
+/// Parses `<number> <unit_expr>`, e.g. the value of a `density` command.
+pub fn parse_quantity(line: &str) -> Result<(f64, UnitExpr), ParseError<'_>> {
     let mut lexer = Lexer::new(line);
-    if let None = lexer.peek() {
-        return Err(ParseError::new(line, None, ParseErrorKind::Empty));
+    let value = lexer.parse_number()?;
+    let start = lexer.pos;
+    let unit = lexer.parse_unit_expr()?;
+    lexer.ensure_eof()?;
+    match unit {
+        UnitTarget::Unit(expr) => Ok((value, expr)),
+        _ => Err(ParseError::new(
+            line,
+            Some(start),
+            ParseErrorKind::AuMustSingle,
+        )),
     }
+}
 
-    let value = lexer.parse_number()?;
-    let from = lexer.parse_unit_expr()?;
-    lexer.consume_kw_to()?;
-    let to = lexer.parse_unit_expr()?;
+/// Parses a bare `<unit_expr>`, e.g. the argument of `simplify`.
+pub fn parse_unit(line: &str) -> Result<UnitExpr, ParseError<'_>> {
+    let mut lexer = Lexer::new(line);
+    let unit = lexer.parse_unit_expr()?;
     lexer.ensure_eof()?;
+    match unit {
+        UnitTarget::Unit(expr) => Ok(expr),
+        _ => Err(ParseError::new(line, Some(0), ParseErrorKind::AuMustSingle)),
+    }
+}
 
-    if let UnitTarget::Unit(expr1) = &from
-        && let UnitTarget::Unit(expr2) = &to
-    {
-        if expr1.dim != expr2.dim {
+pub fn parse_expr(line: &str) -> Result<ConversionExpr, ParseError<'_>> {
+    let mut lexer = Lexer::new(line);
+    if lexer.peek().is_none() {
+        return Err(ParseError::new(line, None, ParseErrorKind::Empty));
+    }
+
+    let number_start = lexer.pos;
+    let (value, exact_value, from) = if lexer.at_duration() {
+        let (value, from) = lexer.parse_duration()?;
+        (value, None, from)
+    } else {
+        let value = lexer.parse_number()?;
+        let exact_value = Ratio::parse(line[number_start..lexer.pos].trim());
+        (value, exact_value, lexer.parse_unit_expr()?)
+    };
+    lexer.skip_ws();
+    let qualifier_pos = lexer.pos;
+    let mut equivalences = equivalence::active(&settings());
+    if let Some(name) = lexer.parse_qualifier()? {
+        let links = substances::equivalences(name);
+        if links.is_empty() {
             return Err(ParseError::new(
                 line,
-                None,
-                ParseErrorKind::IncompatibleDim(expr1.dim, expr2.dim),
+                Some(qualifier_pos),
+                ParseErrorKind::UnknownSubstance(name),
             ));
         }
+        equivalences.extend(links);
     }
-    if let UnitTarget::Au = &from
-        && let UnitTarget::Au = &to
+    lexer.skip_ws();
+    // a bare quantity is shown in its best unit
+    let to = if lexer.peek().is_none() {
+        UnitTarget::Auto
+    } else {
+        lexer.consume_kw_to()?;
+        lexer.parse_target()?
+    };
+    lexer.ensure_eof()?;
+    // resolved here, so that the checks below cover the unit picked
+    let to = match (to, &from) {
+        (UnitTarget::Simplest, UnitTarget::Unit(expr)) => {
+            UnitTarget::Unit(simplify::simplest_unit(expr).unwrap_or_else(|| expr.clone()))
+        }
+        (to, _) => to,
+    };
+
+    let settings = settings();
+    let bridge = match (&from, &to) {
+        (UnitTarget::Unit(expr1), UnitTarget::Unit(expr2))
+            if !settings
+                .system
+                .bridges(expr1.dim, expr2.dim, settings.codata) =>
+        {
+            equivalence::find_bridge(
+                &equivalences,
+                (expr1.dim, expr1.kind),
+                (expr2.dim, expr2.kind),
+            )
+        }
+        _ => None,
+    };
+    let kind = match (&from, &to) {
+        (UnitTarget::Unit(expr1), UnitTarget::Unit(expr2)) => {
+            if let (Some(a), Some(b)) = (expr1.system, expr2.system)
+                && a != b
+                && settings.system != UnitSystem::Gaussian
+            {
+                Some(ParseErrorKind::SystemMismatch(a, b))
+            } else if !settings
+                .system
+                .bridges(expr1.dim, expr2.dim, settings.codata)
+                && bridge.is_none()
+            {
+                Some(ParseErrorKind::IncompatibleDim(expr1.dim, expr2.dim))
+            } else if let (Some(a), Some(b)) = (expr1.kind, expr2.kind)
+                && a != b
+                && expr1.dim == expr2.dim
+                && bridge.is_none()
+                && (settings.strict || a.is_guarded() || b.is_guarded())
+            {
+                Some(ParseErrorKind::IncompatibleKind(a, b))
+            } else {
+                None
+            }
+        }
+        (UnitTarget::Unit(expr), UnitTarget::System(system)) => system
+            .factor(expr.dim, settings.codata)
+            .is_none()
+            .then_some(ParseErrorKind::NoSystemMapping(expr.dim, *system)),
+        (UnitTarget::Unit(expr), UnitTarget::Duration(_)) => {
+            (expr.dim != Dim::time(1)).then_some(ParseErrorKind::NotDuration(expr.dim))
+        }
+        (UnitTarget::Au, UnitTarget::Duration(_)) => Some(ParseErrorKind::NotDuration(Dim::zero())),
+        (UnitTarget::Au, UnitTarget::Au) => Some(ParseErrorKind::AuToAu),
+        (UnitTarget::Au, UnitTarget::Auto) => Some(ParseErrorKind::AuToAuto),
+        (UnitTarget::Au, UnitTarget::Simplest) => Some(ParseErrorKind::AuToSimplest),
+        (UnitTarget::Au, UnitTarget::System(system)) => Some(ParseErrorKind::AuToSystem(*system)),
+        _ => None,
+    };
+    if let Some(kind) = kind {
+        return Err(ParseError::new(line, None, kind));
+    }
+
+    let mut notes = Vec::new();
+    let has_currency =
+        |target: &UnitTarget| matches!(target, UnitTarget::Unit(expr) if expr.dim.currency != 0);
+    if (has_currency(&from) || has_currency(&to))
+        && let Some(note) = currency::note()
+    {
+        notes.push(format!("using {note}"));
+    }
+    let mut warnings = Vec::new();
+    if let (UnitTarget::Unit(expr1), UnitTarget::Unit(expr2)) = (&from, &to)
+        && let (Some(a), Some(b)) = (expr1.kind, expr2.kind)
+        && a != b
+        // a unit system or an equivalence links different dimensions
+        && expr1.dim == expr2.dim
+        && bridge.is_none()
     {
-        return Err(ParseError::new(line, None, ParseErrorKind::AuToAu));
+        warnings.push(format!(
+            "converting {a} to {b}, they only share a dimension (`:set strict on` rejects this)"
+        ));
     }
-    Ok(ConversionExpr { value, from, to })
+    if lexer.bare_au
+        && let (UnitTarget::Au, UnitTarget::Unit(expr)) | (UnitTarget::Unit(expr), UnitTarget::Au) =
+            (&from, &to)
+        && expr.dim == Dim::len(1)
+    {
+        warnings.push(String::from(
+            "`au` is the atomic unit of length (bohr), use `AU`, `ua` or `astro:au` for the astronomical unit",
+        ));
+    }
+    Ok(ConversionExpr {
+        value,
+        exact_value,
+        from,
+        to,
+        bridge,
+        notes,
+        warnings,
+    })
 }
 
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -411,6 +943,9 @@ mod tests {
     fn missing_to_keyword() {
         assert_err("1 km m", Some(5), |k| assert!(matches!(k, ParseErrorKind::MissingTo)));
         assert_err("1 km too m", Some(5), |k| assert!(matches!(k, ParseErrorKind::MissingTo)));
+        assert!(matches!(parse_expr("1 km").unwrap().to, UnitTarget::Auto));
+        assert!(matches!(parse_expr("1 km to auto").unwrap().to, UnitTarget::Auto));
+        assert_err("1 au", None, |k| assert!(matches!(k, ParseErrorKind::AuToAuto)));
     }
 
     #[test]
@@ -427,8 +962,8 @@ mod tests {
             ParseErrorKind::UnexpectedChar('*') => {}
             _ => panic!("unexpected kind: {k:?}"),
         });
-        assert_err("1 (m to m", Some(2), |k| match k {
-            ParseErrorKind::UnexpectedChar('(') => {}
+        assert_err("1 (m to m", Some(5), |k| match k {
+            ParseErrorKind::BadSyntax("expect ')'") => {}
             _ => panic!("unexpected kind: {k:?}"),
         });
     }
@@ -477,4 +1012,144 @@ mod tests {
     fn incompatible_dimension() {
         assert_err("1 km to s", None, |k| assert!(matches!(k, ParseErrorKind::IncompatibleDim(_, _))));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn system_targets() {
+        assert_err("1 au to natural", None, |k| {
+            assert!(matches!(k, ParseErrorKind::AuToSystem(UnitSystem::Natural)))
+        });
+        assert_err("1 A to planck", None, |k| {
+            assert!(matches!(k, ParseErrorKind::NoSystemMapping(_, UnitSystem::Planck)))
+        });
+        for line in ["1 T to gaussian", "1 Wb to gaussian", "1 A/m to gaussian"] {
+            assert_err(line, None, |k| {
+                assert!(matches!(k, ParseErrorKind::NoSystemMapping(_, UnitSystem::Gaussian)))
+            });
+        }
+        assert_err("1 m to natural*m", Some(14), |k| {
+            assert!(matches!(k, ParseErrorKind::BadSyntax(_)))
+        });
+    }
+
+    #[test]
+    fn mixed_systems() {
+        assert_err("1 statC*A to C", Some(7), |k| {
+            assert!(matches!(
+                k,
+                ParseErrorKind::MixedSystems(UnitSystem::Gaussian, UnitSystem::Si)
+            ))
+        });
+        assert_err("1 statC to C", None, |k| {
+            assert!(matches!(
+                k,
+                ParseErrorKind::SystemMismatch(UnitSystem::Gaussian, UnitSystem::Si)
+            ))
+        });
+        // mechanical CGS units are shared
+        assert!(parse_expr("1 erg/s to W*statC/C").is_err());
+        assert!(parse_expr("1 dyn*cm to erg").is_ok());
+    }
+
+    #[test]
+    fn natural_bridges_only_equal_energy_power() {
+        crate::settings::set("system", "natural").unwrap();
+        assert!(parse_expr("1 eV to K").is_ok());
+        assert_err("1 eV to m", None, |k| {
+            assert!(matches!(k, ParseErrorKind::IncompatibleDim(_, _)))
+        });
+        // length and time do not share a dimension, the system links them
+        assert!(parse_expr("1 m to s").unwrap().warnings.is_empty());
+        crate::settings::set("strict", "on").unwrap();
+        assert!(parse_expr("1 m to s").is_ok());
+        assert!(parse_expr("1 J to N*m").is_err());
+    }
+
+    #[test]
+    fn qualified_units() {
+        assert!(parse_expr("1 length:km to m").is_ok());
+        assert_err("1 astro:km to m", Some(2), |k| match k {
+            ParseErrorKind::UnknownUnit(u) => assert_eq!(*u, "astro:km"),
+            _ => panic!("unexpected kind: {k:?}"),
+        });
+    }
+
+    #[test]
+    fn ambiguous_au_warns() {
+        assert_eq!(parse_expr("1 au to km").unwrap().warnings.len(), 1);
+        assert_eq!(parse_expr("1 m to au").unwrap().warnings.len(), 1);
+        assert!(parse_expr("1 au to fs").unwrap().warnings.is_empty());
+        assert!(parse_expr("1 atomic:au to km").unwrap().warnings.is_empty());
+    }
+
+    #[test]
+    fn durations() {
+        assert_err("P1H to s", Some(0), |k| assert!(matches!(k, ParseErrorKind::InvalidDuration)));
+        assert_err("1 m to duration", None, |k| assert!(matches!(k, ParseErrorKind::NotDuration(_))));
+        assert!(parse_expr("-PT1M to s").is_ok());
+    }
+
+    #[test]
+    fn quantity_kinds() {
+        assert_eq!(parse_expr("1 N*m to J").unwrap().warnings.len(), 1);
+        assert_eq!(parse_expr("1 s^-1 to kHz").unwrap().warnings.len(), 0);
+        assert_eq!(parse_expr("1 kN*m to m*N").unwrap().warnings.len(), 0);
+        crate::settings::set("strict", "on").unwrap();
+        assert_err("1 N*m to J", None, |k| {
+            assert!(matches!(
+                k,
+                ParseErrorKind::IncompatibleKind(QuantityKind::Torque, QuantityKind::Energy)
+            ))
+        });
+        assert!(parse_expr("1 kg*m^2/s^2 to J").is_ok());
+    }
+
+    #[test]
+    fn guarded_kinds_always_rejected() {
+        assert_err("1 Gy to Sv", None, |k| {
+            assert!(matches!(
+                k,
+                ParseErrorKind::IncompatibleKind(QuantityKind::AbsorbedDose, QuantityKind::EquivalentDose)
+            ))
+        });
+        assert_err("1 Bq to Hz", None, |k| {
+            assert!(matches!(k, ParseErrorKind::IncompatibleKind(_, _)))
+        });
+        assert!(parse_expr("1 Bq to s^-1").is_ok());
+        assert!(parse_expr("1 Gy to J/kg").is_ok());
+        // rates and per-mass forms keep their kind
+        for line in ["1 mGy/hour to mSv/hour", "1 Gy/s to Sv/s", "1 Bq/kg to Hz/kg"] {
+            assert_err(line, None, |k| assert!(matches!(k, ParseErrorKind::IncompatibleKind(_, _))));
+        }
+        assert!(parse_expr("1 uSv/hour to mSv/year").is_ok());
+        assert!(parse_expr("1 mGy/hour to J/kg/s").is_ok());
+        assert!(parse_expr("1 MBq/L to Bq/mL").is_ok());
+    }
+
+    #[test]
+    fn unit_names() {
+        assert_err("1 ma to A", Some(2), |k| assert!(matches!(k, ParseErrorKind::UnknownUnit("ma"))));
+        assert_err("1 Mg to kg", Some(2), |k| assert!(matches!(k, ParseErrorKind::UnknownUnit("Mg"))));
+        assert_err("1 Mm to km", Some(2), |k| assert!(matches!(k, ParseErrorKind::UnknownUnit("Mm"))));
+        assert_err("1 mPa to Pa", Some(2), |k| assert!(matches!(k, ParseErrorKind::UnknownUnit("mPa"))));
+        assert_err("1 S to s", Some(2), |k| assert!(matches!(k, ParseErrorKind::UnknownUnit("S"))));
+        assert!(parse_expr("1 HECTOPASCALS to Pa").is_ok());
+        assert!(parse_expr("1 degrees  Celsius to K").is_ok());
+        assert_err("1 degrees to K", Some(2), |k| assert!(matches!(k, ParseErrorKind::UnknownUnit(_))));
+    }
+
+    #[test]
+    fn substance_qualifier() {
+        assert_err("1 g of foo to L", Some(4), |k| match k {
+            ParseErrorKind::UnknownSubstance(name) => assert_eq!(*name, "foo"),
+            _ => panic!("unexpected kind: {k:?}"),
+        });
+        assert_err("1 g[water to L", Some(11), |k| assert!(matches!(k, ParseErrorKind::BadSyntax(_))));
+        assert_err("1 g of water to m", None, |k| assert!(matches!(k, ParseErrorKind::IncompatibleDim(_, _))));
+        assert!(parse_expr("1 g to L").is_err());
+        assert_err("1 g of h2o to mol", Some(4), |k| {
+            assert!(matches!(k, ParseErrorKind::UnknownSubstance("h2o")))
+        });
+        assert_err("1 g NaCl to L", None, |k| assert!(matches!(k, ParseErrorKind::IncompatibleDim(_, _))));
+        assert_err("1 g Xx to mol", Some(4), |k| assert!(matches!(k, ParseErrorKind::MissingTo)));
+    }
+}
@@ -0,0 +1,164 @@
+use std::fmt::{self, Display};
+
+/// An exact fraction, for factors such as 0.0254 or 5/9 that an f64 cannot
+/// hold. Operations return `None` when the result no longer fits, and the
+/// caller goes on with floats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ratio {
+    num: i128,
+    /// always positive, and coprime to `num`
+    den: i128,
+}
+
+fn gcd(mut a: i128, mut b: i128) -> i128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a.abs()
+}
+
+impl Ratio {
+    pub const ZERO: Ratio = Ratio { num: 0, den: 1 };
+    pub const ONE: Ratio = Ratio { num: 1, den: 1 };
+
+    pub fn new(num: i128, den: i128) -> Option<Ratio> {
+        if den == 0 {
+            return None;
+        }
+        let g = gcd(num, den);
+        let sign = den.signum();
+        Some(Ratio {
+            num: sign.checked_mul(num / g)?,
+            den: sign.checked_mul(den / g)?,
+        })
+    }
+
+    pub fn integer(n: i128) -> Ratio {
+        Ratio { num: n, den: 1 }
+    }
+
+    /// Reads a decimal number such as `0.0254`, `-12` or `1.5e-3` exactly.
+    pub fn parse(s: &str) -> Option<Ratio> {
+        let (mantissa, exp) = match s.split_once(['e', 'E']) {
+            Some((m, e)) => (m, e.parse::<i32>().ok()?),
+            None => (s, 0),
+        };
+        let (sign, mantissa) = match mantissa.strip_prefix('-') {
+            Some(rest) => (-1, rest),
+            None => (1, mantissa.strip_prefix('+').unwrap_or(mantissa)),
+        };
+        let (int, frac) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        if int.is_empty() && frac.is_empty() {
+            return None;
+        }
+        let digits = format!("{int}{frac}");
+        if !digits.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        let num: i128 = digits.parse().ok()?;
+        let exp = exp.checked_sub(i32::try_from(frac.len()).ok()?)?;
+        Ratio::integer(sign * num).mul(Ratio::integer(10).pow(exp)?)
+    }
+
+    pub fn mul(self, other: Ratio) -> Option<Ratio> {
+        // cross-cancel first, so that only the result has to fit
+        let g1 = gcd(self.num, other.den).max(1);
+        let g2 = gcd(other.num, self.den).max(1);
+        Ratio::new(
+            (self.num / g1).checked_mul(other.num / g2)?,
+            (self.den / g2).checked_mul(other.den / g1)?,
+        )
+    }
+
+    pub fn div(self, other: Ratio) -> Option<Ratio> {
+        if other.num == 0 {
+            return None;
+        }
+        self.mul(Ratio {
+            num: other.den * other.num.signum(),
+            den: other.num.abs(),
+        })
+    }
+
+    pub fn add(self, other: Ratio) -> Option<Ratio> {
+        let g = gcd(self.den, other.den);
+        let num = self
+            .num
+            .checked_mul(other.den / g)?
+            .checked_add(other.num.checked_mul(self.den / g)?)?;
+        Ratio::new(num, self.den.checked_mul(other.den / g)?)
+    }
+
+    pub fn sub(self, other: Ratio) -> Option<Ratio> {
+        self.add(Ratio {
+            num: -other.num,
+            den: other.den,
+        })
+    }
+
+    pub fn pow(self, k: i32) -> Option<Ratio> {
+        let mut base = if k < 0 { Ratio::ONE.div(self)? } else { self };
+        if base == Ratio::ONE || base == Ratio::ZERO || k == 0 {
+            return Some(if k == 0 { Ratio::ONE } else { base });
+        }
+        // by squaring, anything but 0 and ±1 overflows within 127 steps
+        let mut k = k.unsigned_abs();
+        let mut result = Ratio::ONE;
+        while k > 0 {
+            if k & 1 == 1 {
+                result = result.mul(base)?;
+            }
+            k >>= 1;
+            if k > 0 {
+                base = base.mul(base)?;
+            }
+        }
+        Some(result)
+    }
+
+    pub fn to_f64(self) -> f64 {
+        // one rounding when both parts are exact as f64
+        self.num as f64 / self.den as f64
+    }
+}
+
+impl Display for Ratio {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.den == 1 {
+            write!(f, "{}", self.num)
+        } else {
+            write!(f, "{}/{}", self.num, self.den)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn r(num: i128, den: i128) -> Ratio {
+        Ratio::new(num, den).unwrap()
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(Ratio::parse("0.0254"), Some(r(127, 5000)));
+        assert_eq!(Ratio::parse("-1.5e-3"), Some(r(-3, 2000)));
+        assert_eq!(Ratio::parse("1e3"), Some(r(1000, 1)));
+        assert_eq!(Ratio::parse(".5"), Some(r(1, 2)));
+        assert_eq!(Ratio::parse("x"), None);
+        assert_eq!(r(1, 3).add(r(1, 6)), Some(r(1, 2)));
+        assert_eq!(r(1, 3).sub(r(1, 2)), Some(r(-1, 6)));
+        assert_eq!(r(2, 3).div(r(-4, 9)), Some(r(-3, 2)));
+        assert_eq!(r(2, 3).pow(-2), Some(r(9, 4)));
+        assert_eq!(r(4184, 1).mul(r(1, 4184)), Some(Ratio::ONE));
+        assert_eq!(r(1, 3).to_string(), "1/3");
+        assert_eq!(r(6, 3).to_string(), "2");
+        // 10^40 does not fit
+        assert_eq!(Ratio::integer(10).pow(40), None);
+        assert_eq!(Ratio::integer(-1).pow(2_000_000_001), Some(Ratio::integer(-1)));
+        assert_eq!(Ratio::ONE.pow(i32::MIN), Some(Ratio::ONE));
+        assert_eq!(Ratio::ZERO.pow(0), Some(Ratio::ONE));
+        assert_eq!(r(1, 2).pow(i32::MAX), None);
+    }
+}
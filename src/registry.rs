@@ -0,0 +1,1180 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display};
+use std::fs;
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+use crate::constants::Codata;
+use crate::defs::{self, AutoDecl, DefError, DefErrorKind, Definitions, UnitDecl, Value};
+use crate::rational::Ratio;
+use crate::settings::config_dir;
+use crate::systems::UnitSystem;
+use crate::units::{Dim, Exact, QuantityKind, UnitDef};
+
+/// The built-in catalog, see the file for its format.
+pub static BUILTIN: &str = include_str!("units.def");
+
+// Levenshtein distance, counted in chars
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (diagonal + usize::from(ca != cb))
+                .min(above + 1)
+                .min(row[j] + 1);
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+/// Units listed together. Several groups may share a name, e.g. the
+/// luminance and the illuminance units are both `photometry`.
+#[derive(Debug, Clone)]
+pub struct Group {
+    pub name: String,
+    /// short name accepted in `<group>:<unit>`, e.g. `astro`
+    pub short: Option<String>,
+    /// dimension all its units have
+    pub dim: Option<Dim>,
+    pub kind: Option<QuantityKind>,
+    pub system: Option<UnitSystem>,
+    /// symbols in definition order
+    pub units: Vec<String>,
+    /// names only valid as `<group>:<name>`, with the symbol they stand for
+    local: Vec<(String, String)>,
+}
+
+/// Where a unit was defined.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Builtin,
+    /// with `def`, saved in the config dir
+    User,
+    /// from a GNU units or pint file
+    Import(PathBuf),
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Builtin => write!(f, "built-in"),
+            Source::User => write!(f, "user, saved in units.def"),
+            Source::Import(path) => write!(f, "imported from {}", path.display()),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Unit {
+    pub symbol: String,
+    pub def: UnitDef,
+    /// the right-hand side of its definition, e.g. `1000 W*hour`
+    pub definition: String,
+    /// full names, plurals and spellings, the full name first
+    pub names: Vec<String>,
+    pub description: Option<String>,
+    pub source: Source,
+}
+
+/// All known units, their names and the groups they are listed in.
+#[derive(Default)]
+pub struct Registry {
+    groups: Vec<Group>,
+    units: HashMap<String, Unit>,
+    /// full names, plurals and spelling variants, to symbols
+    names: HashMap<String, String>,
+    /// lower-cased symbols and names, to the symbols they may stand for
+    folded: HashMap<String, Vec<String>>,
+    /// the user's definitions, as they are saved
+    user: Vec<UnitDecl>,
+    /// imported files, as far as they loaded
+    imports: Vec<(Definitions, Source)>,
+    /// `to auto` preferences, later lines win
+    auto: Vec<AutoDecl>,
+}
+
+#[derive(Debug, Error)]
+pub enum UserDefError {
+    #[error("Cannot read {0}: {1}")]
+    Read(PathBuf, std::io::Error),
+
+    #[error("Cannot write {0}: {1}")]
+    Write(PathBuf, std::io::Error),
+
+    #[error("Invalid definitions in {0}, {1}")]
+    File(PathBuf, DefError),
+
+    #[error("Invalid definition: {0}")]
+    Invalid(DefErrorKind),
+
+    #[error("{0} is a built-in unit, use `def!` to redefine it")]
+    Builtin(String),
+
+    #[error("{0} cannot be defined by {1}, which is built from {0}")]
+    BuiltFrom(String, String),
+
+    #[error("{0} is not a user-defined unit")]
+    NotUser(String),
+
+    #[error("Cannot remove {0}, another definition needs it: {1}")]
+    InUse(String, DefErrorKind),
+
+    #[error(
+        "{0} did not load, so it is left as it is and this session's definitions are not saved"
+    )]
+    Unsaved(PathBuf),
+}
+
+thread_local! {
+    static REGISTRY: RefCell<Registry> = RefCell::new(Registry::builtin());
+    /// the user's file did not load, so saving would lose what is in it
+    static USER_FILE_BROKEN: Cell<bool> = const { Cell::new(false) };
+}
+
+pub enum Resolved {
+    Unit(String),
+    /// a case-insensitive match with more than one candidate
+    Ambiguous(Vec<String>),
+    Unknown,
+}
+
+#[derive(Clone)]
+enum Rhs {
+    Expr(String),
+    /// a prefix factor times another unit
+    Prefixed(f64, String),
+}
+
+// a unit of a definitions file before its right-hand side is evaluated
+struct Pending {
+    line: usize,
+    symbol: String,
+    group: usize,
+    rhs: Rhs,
+    names: Vec<String>,
+    description: Option<String>,
+    zero: Option<String>,
+    local: Vec<String>,
+    force: bool,
+}
+
+// what a unit already loaded stands for in a definition
+fn value(unit: &Unit) -> Value {
+    Value {
+        factor: unit.def.factor,
+        dim: unit.def.dim,
+        scale: unit.def.scale,
+        exact: unit.def.exact.map(|exact| exact.factor),
+    }
+}
+
+// evaluates definitions depth first, so that they may refer to units
+// defined further down
+struct Resolver<'r> {
+    registry: &'r Registry,
+    pending: HashMap<String, (usize, Rhs)>,
+    done: HashMap<String, Value>,
+    failed: HashMap<String, DefError>,
+    stack: Vec<String>,
+    /// for imports: a unit built from one that fails is unknown on its own
+    /// line, rather than failing with the other unit's error
+    lenient: bool,
+}
+
+impl Resolver<'_> {
+    fn resolve(&mut self, symbol: &str, line: usize) -> Result<Value, DefError> {
+        if let Some(&value) = self.done.get(symbol) {
+            return Ok(value);
+        }
+        if let Some(e) = self.failed.get(symbol) {
+            return Err(e.clone());
+        }
+        let Some((decl_line, rhs)) = self.pending.get(symbol) else {
+            return match self.registry.lookup(symbol) {
+                Some(unit) => Ok(value(unit)),
+                None => Err(DefError::new(
+                    line,
+                    DefErrorKind::UnknownUnit(symbol.into()),
+                )),
+            };
+        };
+        let (decl_line, rhs) = (*decl_line, rhs.clone());
+        if let Some(i) = self.stack.iter().position(|s| s == symbol) {
+            let mut cycle = self.stack[i..].to_vec();
+            cycle.push(symbol.to_string());
+            return Err(DefError::new(
+                decl_line,
+                DefErrorKind::Cycle(cycle.join(" -> ")),
+            ));
+        }
+        self.stack.push(symbol.to_string());
+        let value = self.evaluate(rhs, decl_line);
+        self.stack.pop();
+        match value {
+            Ok(value) => {
+                self.done.insert(symbol.to_string(), value);
+                Ok(value)
+            }
+            Err(e) => {
+                self.failed.insert(symbol.to_string(), e.clone());
+                Err(e)
+            }
+        }
+    }
+
+    fn evaluate(&mut self, rhs: Rhs, line: usize) -> Result<Value, DefError> {
+        Ok(match rhs {
+            Rhs::Expr(expr) => defs::eval(&expr, line, &mut |name| self.dependency(name, line))?,
+            Rhs::Prefixed(factor, base) => {
+                let base = self.dependency(&base, line)?;
+                // prefix factors are decimals, so their shortest form is exact
+                let exact = Ratio::parse(&format!("{factor:e}"));
+                Value {
+                    factor: factor * base.factor,
+                    exact: exact.zip(base.exact).and_then(|(a, b)| a.mul(b)),
+                    ..base
+                }
+            }
+        })
+    }
+
+    // a unit that the definition on `line` is built from
+    fn dependency(&mut self, name: &str, line: usize) -> Result<Value, DefError> {
+        match self.resolve(name, line) {
+            Err(e) if self.lenient && e.line != line => {
+                Err(DefError::new(line, DefErrorKind::UnknownUnit(name.into())))
+            }
+            value => value,
+        }
+    }
+}
+
+// every unit of `defs` with its prefixed forms, groups counted from
+// `first_group`
+fn pending(defs: &Definitions, first_group: usize) -> Vec<Pending> {
+    let mut pending = Vec::new();
+    for decl in &defs.units {
+        pending.push(Pending {
+            line: decl.line,
+            symbol: decl.symbol.clone(),
+            group: first_group + decl.group,
+            rhs: Rhs::Expr(decl.expr.clone()),
+            names: decl.names.clone(),
+            description: decl.desc.clone(),
+            zero: decl.zero.clone(),
+            local: decl.local.clone(),
+            force: decl.force,
+        });
+        for prefix in &decl.prefixes {
+            let names = decl
+                .names
+                .iter()
+                .filter(|name| name.is_ascii())
+                .map(|name| format!("{}{name}", prefix.name))
+                .collect();
+            pending.push(Pending {
+                line: decl.line,
+                symbol: format!("{}{}", prefix.symbol, decl.symbol),
+                group: first_group + decl.group,
+                rhs: Rhs::Prefixed(prefix.factor, decl.symbol.clone()),
+                names,
+                description: None,
+                zero: None,
+                local: Vec::new(),
+                force: decl.force,
+            });
+        }
+    }
+    pending
+}
+
+impl Registry {
+    /// The built-in catalog. It is checked by the tests, so a broken
+    /// `units.def` never gets this far.
+    pub fn builtin() -> Registry {
+        let mut registry = Registry::default();
+        if let Err(e) = registry.load(BUILTIN, Source::Builtin) {
+            panic!("units.def: {e}");
+        }
+        registry
+    }
+
+    /// Adds the units of a definitions file. Nothing is added when any of
+    /// them is invalid.
+    pub fn load(&mut self, text: &str, source: Source) -> Result<(), DefError> {
+        self.add(defs::parse(text)?, source)
+    }
+
+    pub fn add(&mut self, defs: Definitions, source: Source) -> Result<(), DefError> {
+        let first_group = self.groups.len();
+        let pending = pending(&defs, first_group);
+
+        let mut resolver = Resolver {
+            registry: self,
+            pending: HashMap::new(),
+            done: HashMap::new(),
+            failed: HashMap::new(),
+            stack: Vec::new(),
+            lenient: false,
+        };
+        for p in &pending {
+            if (self.units.contains_key(&p.symbol) && !p.force)
+                || resolver.pending.contains_key(&p.symbol)
+            {
+                return Err(DefError::new(
+                    p.line,
+                    DefErrorKind::Duplicate(p.symbol.clone()),
+                ));
+            }
+            if !p.force
+                && let Some(other) = self.names.get(&p.symbol)
+            {
+                return Err(DefError::new(
+                    p.line,
+                    DefErrorKind::NameTaken(p.symbol.clone(), other.clone()),
+                ));
+            }
+            resolver
+                .pending
+                .insert(p.symbol.clone(), (p.line, p.rhs.clone()));
+        }
+
+        let mut groups = Vec::new();
+        for decl in &defs.groups {
+            let dim = match &decl.dim {
+                Some(expr) => Some(
+                    defs::eval(expr, decl.line, &mut |name| {
+                        resolver.resolve(name, decl.line)
+                    })?
+                    .dim,
+                ),
+                None => None,
+            };
+            groups.push(Group {
+                name: decl.name.clone(),
+                short: decl.short.clone(),
+                dim,
+                kind: decl.kind,
+                system: decl.system,
+                units: Vec::new(),
+                local: Vec::new(),
+            });
+        }
+
+        let mut units = Vec::new();
+        for p in &pending {
+            let value = resolver.resolve(&p.symbol, p.line)?;
+            let group = &mut groups[p.group - first_group];
+            if let Some(dim) = group.dim
+                && dim != value.dim
+            {
+                return Err(DefError::new(
+                    p.line,
+                    DefErrorKind::DimensionMismatch(p.symbol.clone(), value.dim, dim),
+                ));
+            }
+            let (offset, exact_offset) = match &p.zero {
+                Some(expr) => {
+                    let zero =
+                        defs::eval(expr, p.line, &mut |name| resolver.resolve(name, p.line))?;
+                    if zero.dim != Dim::zero() || zero.scale.is_some() {
+                        return Err(DefError::new(
+                            p.line,
+                            DefErrorKind::Syntax(String::from("zero must be a plain number")),
+                        ));
+                    }
+                    (zero.factor, zero.exact)
+                }
+                None => (0.0, Some(Ratio::ZERO)),
+            };
+            group.units.push(p.symbol.clone());
+            group
+                .local
+                .extend(p.local.iter().map(|name| (name.clone(), p.symbol.clone())));
+            let system = group
+                .system
+                .or((value.dim.curr != 0).then_some(UnitSystem::Si));
+            let definition = match &p.rhs {
+                Rhs::Expr(expr) => expr.clone(),
+                Rhs::Prefixed(factor, base) => format!("{factor:e} {base}"),
+            };
+            units.push(Unit {
+                symbol: p.symbol.clone(),
+                definition,
+                names: p
+                    .names
+                    .iter()
+                    .filter(|n| **n != p.symbol)
+                    .cloned()
+                    .collect(),
+                source: source.clone(),
+                def: UnitDef {
+                    dim: value.dim,
+                    factor: value.factor,
+                    scale: value.scale,
+                    system,
+                    kind: group.kind,
+                    offset,
+                    exact: value
+                        .exact
+                        .zip(exact_offset)
+                        .map(|(factor, offset)| Exact { factor, offset }),
+                },
+                description: p.description.clone(),
+            });
+        }
+
+        // names may never shadow a symbol or stand for two units
+        let mut names = self.names.clone();
+        for p in pending.iter().filter(|p| p.force) {
+            names.remove(&p.symbol);
+        }
+        let all_names = pending.iter().map(|p| (p.line, &p.symbol, &p.names)).chain(
+            defs.names
+                .iter()
+                .map(|(line, symbol, names)| (*line, symbol, names)),
+        );
+        for (line, symbol, list) in all_names {
+            if !self.units.contains_key(symbol) && !resolver.pending.contains_key(symbol) {
+                return Err(DefError::new(
+                    line,
+                    DefErrorKind::UnknownUnit(symbol.clone()),
+                ));
+            }
+            for name in list.iter().filter(|name| *name != symbol) {
+                let owner = if resolver.pending.contains_key(name) {
+                    Some(&source)
+                } else {
+                    self.units.get(name).map(|unit| &unit.source)
+                };
+                if let Some(owner) = owner {
+                    return Err(DefError::new(
+                        line,
+                        DefErrorKind::NameIsSymbol(name.clone(), owner.to_string()),
+                    ));
+                }
+                match names.get(name) {
+                    Some(other) if other != symbol => {
+                        return Err(DefError::new(
+                            line,
+                            DefErrorKind::NameTaken(name.clone(), other.clone()),
+                        ));
+                    }
+                    _ => {
+                        names.insert(name.clone(), symbol.clone());
+                    }
+                }
+            }
+        }
+
+        // `to auto` preferences name groups and units defined by now
+        for decl in &defs.auto {
+            let known_group = self
+                .groups
+                .iter()
+                .chain(&groups)
+                .any(|g| g.name == decl.group);
+            if !known_group {
+                return Err(DefError::new(
+                    decl.line,
+                    DefErrorKind::Syntax(format!("unknown group {}", decl.group)),
+                ));
+            }
+            for symbol in decl.units.iter().chain(&decl.excluded) {
+                if !self.units.contains_key(symbol) && !resolver.pending.contains_key(symbol) {
+                    return Err(DefError::new(
+                        decl.line,
+                        DefErrorKind::UnknownUnit(symbol.clone()),
+                    ));
+                }
+            }
+        }
+
+        for p in pending.iter().filter(|p| p.force) {
+            for group in &mut self.groups {
+                group.units.retain(|symbol| *symbol != p.symbol);
+            }
+        }
+        self.groups.extend(groups);
+        self.units
+            .extend(units.into_iter().map(|unit| (unit.symbol.clone(), unit)));
+        for (_, symbol, list) in &defs.names {
+            let unit = self.units.get_mut(symbol).unwrap();
+            for name in list {
+                if !unit.names.contains(name) {
+                    unit.names.push(name.clone());
+                }
+            }
+        }
+        self.names = names;
+        self.auto.extend(defs.auto);
+        self.fold();
+        Ok(())
+    }
+
+    // the lines of `defs` that `add` would stop at for their own unit, in
+    // one pass: symbols already taken, and right-hand sides that do not
+    // resolve, also through another unit of `defs` that does not
+    fn failures(&self, defs: &Definitions) -> Vec<DefError> {
+        let mut errors: Vec<DefError> = Vec::new();
+        let mut resolver = Resolver {
+            registry: self,
+            pending: HashMap::new(),
+            done: HashMap::new(),
+            failed: HashMap::new(),
+            stack: Vec::new(),
+            lenient: true,
+        };
+        let mut kept = Vec::new();
+        for p in pending(defs, 0) {
+            let taken = (self.units.contains_key(&p.symbol) && !p.force)
+                || resolver.pending.contains_key(&p.symbol);
+            if taken {
+                errors.push(DefError::new(p.line, DefErrorKind::Duplicate(p.symbol)));
+            } else if !p.force
+                && let Some(other) = self.names.get(&p.symbol)
+            {
+                errors.push(DefError::new(
+                    p.line,
+                    DefErrorKind::NameTaken(p.symbol, other.clone()),
+                ));
+            } else {
+                resolver
+                    .pending
+                    .insert(p.symbol.clone(), (p.line, p.rhs.clone()));
+                kept.push(p);
+            }
+        }
+        for p in &kept {
+            if let Err(e) = resolver.resolve(&p.symbol, p.line) {
+                errors.push(DefError::new(p.line, e.kind));
+            }
+        }
+        let mut lines = HashSet::new();
+        errors.retain(|e| lines.insert(e.line));
+        errors
+    }
+
+    /// Adds the units of an imported file, leaving out the ones that do
+    /// not load. Returns how many were added, and the lines left out with
+    /// the reason.
+    pub fn import(
+        &mut self,
+        mut defs: Definitions,
+        source: Source,
+    ) -> (usize, Vec<(usize, String)>) {
+        let failures = self.failures(&defs);
+        defs.units
+            .retain(|decl| failures.iter().all(|e| e.line != decl.line));
+        let mut errors: Vec<(usize, String)> = failures
+            .into_iter()
+            .map(|e| (e.line, e.kind.to_string()))
+            .collect();
+        // what is left to find is rare: clashing names, a unit outside the
+        // dimension of its group
+        while !defs.units.is_empty() {
+            let Err(e) = self.add(defs.clone(), source.clone()) else {
+                break;
+            };
+            let count = defs.units.len();
+            defs.units.retain(|decl| decl.line != e.line);
+            errors.push((e.line, e.kind.to_string()));
+            if defs.units.len() == count {
+                defs.units.clear();
+            }
+        }
+        let count = defs.units.len();
+        if count > 0 {
+            self.imports.push((defs, source));
+        }
+        (count, errors)
+    }
+
+    /// The built-in catalog with this registry's imports and the given user
+    /// definitions on top.
+    fn with_user(&self, user: Vec<UnitDecl>) -> Result<Registry, DefError> {
+        let mut registry = Registry::builtin();
+        for (defs, source) in &self.imports {
+            registry.add(defs.clone(), source.clone())?;
+        }
+        registry.load(&render_user(&user), Source::User)?;
+        registry.imports = self.imports.clone();
+        registry.user = user;
+        Ok(registry)
+    }
+
+    // symbols are left out: with prefixes a change of case is a different
+    // unit, `Mm` is no `mm` and `mPa` no `MPa`
+    fn fold(&mut self) {
+        self.folded.clear();
+        for (name, symbol) in &self.names {
+            let symbols = self.folded.entry(name.to_lowercase()).or_default();
+            if !symbols.contains(symbol) {
+                symbols.push(symbol.clone());
+            }
+        }
+        for symbols in self.folded.values_mut() {
+            symbols.sort();
+        }
+    }
+
+    pub fn groups(&self) -> &[Group] {
+        &self.groups
+    }
+
+    /// Full names and spellings, with the symbol each stands for.
+    pub fn names(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.names
+            .iter()
+            .map(|(name, symbol)| (name.as_str(), symbol.as_str()))
+    }
+
+    pub fn unit(&self, symbol: &str) -> Option<&Unit> {
+        self.units.get(symbol)
+    }
+
+    /// The unit with this symbol or name, without case folding.
+    pub fn lookup(&self, name: &str) -> Option<&Unit> {
+        self.unit(name)
+            .or_else(|| self.names.get(name).and_then(|symbol| self.unit(symbol)))
+    }
+
+    /// The group a unit is listed in.
+    pub fn group_of(&self, symbol: &str) -> Option<&Group> {
+        self.groups
+            .iter()
+            .rev()
+            .find(|group| group.units.iter().any(|s| s == symbol))
+    }
+
+    /// Profiles named by `[auto <profile>]` sections.
+    pub fn profiles(&self) -> Vec<&str> {
+        let mut profiles: Vec<&str> = self
+            .auto
+            .iter()
+            .filter_map(|d| d.profile.as_deref())
+            .collect();
+        profiles.sort();
+        profiles.dedup();
+        profiles
+    }
+
+    /// The unit `to auto` writes `si_value` in, starting from the unit it
+    /// was given in. The candidates are the units of its group that are a
+    /// power of 1000 apart from it, such as `um`, `mm`, `m` and `km`, or the
+    /// ones an `[auto]` line lists for the group; the line for `profile`
+    /// replaces the general one. Of these it takes the largest that keeps
+    /// the value at least 1, which lands it in [1, 1000) for prefixes.
+    /// Compound units fall back to the group's coherent SI unit, and are
+    /// kept as they are when there is none.
+    pub fn auto_unit(
+        &self,
+        from: &str,
+        dim: Dim,
+        si_value: f64,
+        profile: Option<&str>,
+        codata: &Codata,
+    ) -> Option<&Unit> {
+        let coherent = || {
+            self.groups
+                .iter()
+                .filter(|group| group.dim == Some(dim))
+                .flat_map(|group| &group.units)
+                .filter_map(|symbol| self.unit(symbol))
+                .find(|unit| unit.def.si_factor(codata) == 1.0 && unit.def.offset == 0.0)
+        };
+        let anchor = match self.resolve(from) {
+            Resolved::Unit(symbol) => self.unit(&symbol).filter(|unit| unit.def.dim == dim),
+            _ => None,
+        };
+        let anchor = anchor.or_else(coherent)?;
+        // temperature scales are only ever shown as they were given
+        if anchor.def.offset != 0.0 || si_value == 0.0 {
+            return Some(anchor);
+        }
+        let group = self.group_of(&anchor.symbol)?;
+        let decl = |profile: Option<&str>| {
+            self.auto
+                .iter()
+                .rev()
+                .find(|d| d.group == group.name && d.profile.as_deref() == profile)
+        };
+        let decl = profile.and_then(|p| decl(Some(p))).or_else(|| decl(None));
+        let excluded = |unit: &Unit| decl.is_some_and(|d| d.excluded.contains(&unit.symbol));
+        let anchor = if excluded(anchor) {
+            coherent().filter(|unit| !excluded(unit))?
+        } else {
+            anchor
+        };
+        let anchor_factor = anchor.def.si_factor(codata);
+        let mut candidates: Vec<&Unit> = match decl.filter(|d| !d.units.is_empty()) {
+            Some(decl) => decl.units.iter().filter_map(|s| self.unit(s)).collect(),
+            None => self
+                .groups
+                .iter()
+                .filter(|g| g.name == group.name && g.kind == group.kind)
+                .flat_map(|g| &g.units)
+                .filter_map(|symbol| self.unit(symbol))
+                .filter(|unit| {
+                    let steps = (unit.def.si_factor(codata) / anchor_factor).log10() / 3.0;
+                    (steps - steps.round()).abs() < 1e-9
+                })
+                .collect(),
+        };
+        candidates.retain(|unit| unit.def.dim == dim && unit.def.offset == 0.0 && !excluded(unit));
+        // of units with the same factor, the one given wins, then group order
+        candidates.sort_by(|a, b| {
+            a.def
+                .si_factor(codata)
+                .total_cmp(&b.def.si_factor(codata))
+                .then_with(|| (a.symbol != anchor.symbol).cmp(&(b.symbol != anchor.symbol)))
+        });
+        candidates.dedup_by(|a, b| a.def.si_factor(codata) == b.def.si_factor(codata));
+        candidates
+            .iter()
+            .rev()
+            .find(|unit| si_value.abs() / unit.def.si_factor(codata) >= 1.0)
+            .or(candidates.first())
+            .copied()
+            .or(Some(anchor))
+    }
+
+    /// Units whose symbol, names or description match `text`, best matches
+    /// first, each with the text it matched. Exact matches rank first, then
+    /// names starting with `text`, containing it, descriptions containing
+    /// it, and finally names one or two typos away.
+    pub fn search(&self, text: &str) -> Vec<(&Unit, String)> {
+        let text = text.to_lowercase();
+        let mut found: Vec<(usize, &Unit, String)> = Vec::new();
+        for unit in self.units.values() {
+            let candidates = std::iter::once(&unit.symbol).chain(&unit.names);
+            let mut best: Option<(usize, String)> = None;
+            for candidate in candidates {
+                let folded = candidate.to_lowercase();
+                let rank = if folded == text {
+                    0
+                } else if folded.starts_with(&text) {
+                    1
+                } else if folded.contains(&text) {
+                    2
+                } else if text.chars().count() > 3
+                    && edit_distance(&folded, &text) <= text.chars().count() / 4
+                {
+                    4
+                } else {
+                    continue;
+                };
+                if best.as_ref().is_none_or(|(r, _)| rank < *r) {
+                    best = Some((rank, candidate.clone()));
+                }
+            }
+            if let Some(description) = &unit.description
+                && description.to_lowercase().contains(&text)
+                && best.as_ref().is_none_or(|(r, _)| *r > 3)
+            {
+                best = Some((3, description.clone()));
+            }
+            if let Some((rank, matched)) = best {
+                found.push((rank, unit, matched));
+            }
+        }
+        found.sort_by(|a, b| (a.0, a.2.len(), &a.1.symbol).cmp(&(b.0, b.2.len(), &b.1.symbol)));
+        found
+            .into_iter()
+            .map(|(_, unit, matched)| (unit, matched))
+            .collect()
+    }
+
+    /// Finds the unit symbol `name` stands for: an exact symbol first, then
+    /// a name, then a name ignoring case when that leaves one unit. Symbols
+    /// are never case-folded, so `Mg`, `Mm` or `KM` are unknown rather than
+    /// the unit of the opposite prefix.
+    pub fn resolve(&self, name: &str) -> Resolved {
+        if self.units.contains_key(name) {
+            return Resolved::Unit(name.to_string());
+        }
+        if let Some(symbol) = self.names.get(name) {
+            return Resolved::Unit(symbol.clone());
+        }
+        match self.folded.get(&name.to_lowercase()).map(Vec::as_slice) {
+            Some([symbol]) => Resolved::Unit(symbol.clone()),
+            Some(symbols) if !symbols.is_empty() => Resolved::Ambiguous(symbols.to_vec()),
+            _ => Resolved::Unknown,
+        }
+    }
+
+    /// Looks up `name` among the units of one group, e.g. `astro:au`.
+    pub fn qualified(&self, namespace: &str, name: &str) -> Option<&Unit> {
+        self.groups
+            .iter()
+            .filter(|g| g.name == namespace || g.short.as_deref() == Some(namespace))
+            .find_map(|g| {
+                let symbol = g
+                    .local
+                    .iter()
+                    .find(|(local, _)| local == name)
+                    .map_or(name, |(_, symbol)| symbol.as_str());
+                g.units.iter().find(|s| *s == symbol)
+            })
+            .and_then(|symbol| self.unit(symbol))
+    }
+}
+
+fn render_user(user: &[UnitDecl]) -> String {
+    let mut text = String::from(
+        "# Units added with `def` in unitrepl, in the format of its built-in\n\
+         # units.def. `def` and `undef` rewrite this file.\n[user]\n",
+    );
+    for decl in user {
+        text.push_str(&decl.render());
+    }
+    text
+}
+
+fn user_file() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("units.def"))
+}
+
+fn replace(registry: Registry) {
+    REGISTRY.with(|r| *r.borrow_mut() = registry);
+}
+
+// what the parser accepts as one identifier
+pub fn is_valid_symbol(symbol: &str) -> bool {
+    let ascii = symbol.starts_with(|c: char| c.is_ascii_alphabetic())
+        && symbol
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.');
+    let cjk = !symbol.is_empty() && symbol.chars().all(|c| !c.is_ascii() && c.is_alphabetic());
+    ascii || cjk
+}
+
+// whether `expr` mentions `symbol`, or a unit defined from it
+fn mentions(r: &Registry, expr: &str, symbol: &str, seen: &mut Vec<String>) -> bool {
+    let mut found = false;
+    // the values are only there to keep `eval` going, errors are left to
+    // loading the definition
+    let _ = defs::eval(expr, 0, &mut |name| {
+        let unit = r
+            .lookup(name)
+            .ok_or_else(|| DefError::new(0, DefErrorKind::UnknownUnit(name.into())))?;
+        if !found && !seen.contains(&unit.symbol) {
+            seen.push(unit.symbol.clone());
+            found = unit.symbol == symbol || mentions(r, &unit.definition, symbol, seen);
+        }
+        Ok(value(unit))
+    });
+    found
+}
+
+/// Adds or replaces a user definition such as `furlong = 201.168 m`, with
+/// the dimension of its right-hand side. Built-in units and names are only
+/// replaced when `force` is set.
+pub fn define(line: &str, force: bool) -> Result<Unit, UserDefError> {
+    let invalid = |e: DefError| UserDefError::Invalid(e.kind);
+    let mut decl = defs::parse(&format!("[user]\n{line}"))
+        .map_err(invalid)?
+        .units
+        .pop()
+        .ok_or_else(|| {
+            UserDefError::Invalid(DefErrorKind::Syntax(String::from(
+                "expected `<symbol> = <expression>`",
+            )))
+        })?;
+    if !is_valid_symbol(&decl.symbol) {
+        return Err(UserDefError::Invalid(DefErrorKind::Syntax(format!(
+            "{} is not a valid unit name",
+            decl.symbol
+        ))));
+    }
+    let registry = with(|r| {
+        let builtin = |symbol: &str| r.unit(symbol).is_some_and(|u| u.source == Source::Builtin);
+        let shadows =
+            builtin(&decl.symbol) || r.names.get(&decl.symbol).is_some_and(|s| builtin(s));
+        if shadows && !force {
+            return Err(UserDefError::Builtin(decl.symbol.clone()));
+        }
+        // built-in units keep the value they were loaded with, so `def! m =
+        // 2 ft` would leave `ft` at 0.3048 of the old metre
+        if shadows {
+            let mut built_from = None;
+            let _ = defs::eval(&decl.expr, 0, &mut |name| {
+                let unit = r
+                    .lookup(name)
+                    .ok_or_else(|| DefError::new(0, DefErrorKind::UnknownUnit(name.into())))?;
+                if built_from.is_none()
+                    && unit.symbol != decl.symbol
+                    && mentions(r, &unit.definition, &decl.symbol, &mut Vec::new())
+                {
+                    built_from = Some(unit.symbol.clone());
+                }
+                Ok(value(unit))
+            });
+            if let Some(unit) = built_from {
+                return Err(UserDefError::BuiltFrom(decl.symbol.clone(), unit));
+            }
+        }
+        decl.force = shadows;
+        let mut user = r.user.clone();
+        user.retain(|d| d.symbol != decl.symbol);
+        user.push(decl.clone());
+        r.with_user(user).map_err(invalid)
+    })?;
+    let unit = registry.unit(&decl.symbol).cloned();
+    replace(registry);
+    Ok(unit.expect("a unit that was just defined"))
+}
+
+/// Removes a user definition. A built-in unit it replaced comes back.
+pub fn undef(symbol: &str) -> Result<(), UserDefError> {
+    let registry = with(|r| {
+        if !r.user.iter().any(|d| d.symbol == symbol) {
+            return Err(UserDefError::NotUser(symbol.to_string()));
+        }
+        let user = r
+            .user
+            .iter()
+            .filter(|d| d.symbol != symbol)
+            .cloned()
+            .collect();
+        r.with_user(user)
+            .map_err(|e| UserDefError::InUse(symbol.to_string(), e.kind))
+    })?;
+    replace(registry);
+    Ok(())
+}
+
+/// Loads the user's definitions from `units.def` in the config dir. When
+/// they do not load, the built-in units stay as they are and `save_user`
+/// leaves the file alone.
+pub fn load_user() -> Result<(), UserDefError> {
+    let Some(path) = user_file().filter(|path| path.is_file()) else {
+        return Ok(());
+    };
+    USER_FILE_BROKEN.with(|broken| broken.set(true));
+    let text = fs::read_to_string(&path).map_err(|e| UserDefError::Read(path.clone(), e))?;
+    let file_err = |e| UserDefError::File(path.clone(), e);
+    let mut registry = Registry::builtin();
+    registry.load(&text, Source::User).map_err(file_err)?;
+    registry.user = defs::parse(&text).map_err(file_err)?.units;
+    replace(registry);
+    USER_FILE_BROKEN.with(|broken| broken.set(false));
+    Ok(())
+}
+
+/// Writes the user's definitions to `units.def` in the config dir.
+pub fn save_user() -> Result<(), UserDefError> {
+    let Some(path) = user_file() else {
+        return Ok(());
+    };
+    if USER_FILE_BROKEN.with(Cell::get) {
+        return Err(UserDefError::Unsaved(path));
+    }
+    let text = with(|r| render_user(&r.user));
+    let write_err = |e| UserDefError::Write(path.clone(), e);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(write_err)?;
+    }
+    fs::write(&path, text).map_err(write_err)
+}
+
+/// Runs `f` on the session's registry.
+pub fn with<R>(f: impl FnOnce(&Registry) -> R) -> R {
+    REGISTRY.with(|r| f(&r.borrow()))
+}
+
+pub fn with_mut<R>(f: impl FnOnce(&mut Registry) -> R) -> R {
+    REGISTRY.with(|r| f(&mut r.borrow_mut()))
+}
+
+pub fn resolve(name: &str) -> Resolved {
+    with(|r| r.resolve(name))
+}
+
+/// Whether `name` is a unit at all, in any of the accepted spellings.
+pub fn is_unit(name: &str) -> bool {
+    !matches!(resolve(name), Resolved::Unknown)
+}
+
+pub fn unit_def(symbol: &str) -> Option<UnitDef> {
+    with(|r| r.unit(symbol).map(|unit| unit.def))
+}
+
+pub fn qualified_unit(namespace: &str, name: &str) -> Option<UnitDef> {
+    with(|r| r.qualified(namespace, name).map(|unit| unit.def))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::CODATA_2018;
+
+    fn symbol(name: &str) -> Option<String> {
+        match resolve(name) {
+            Resolved::Unit(symbol) => Some(symbol),
+            _ => None,
+        }
+    }
+
+    fn load_err(text: &str) -> DefError {
+        let mut registry = Registry::builtin();
+        let err = registry.load(text, Source::User).unwrap_err();
+        assert!(registry.unit("x").is_none(), "{text} was partly loaded");
+        err
+    }
+
+    #[test]
+    fn builtin_catalog() {
+        let factor = |symbol: &str| unit_def(symbol).unwrap().si_factor(&CODATA_2018);
+        let close = |a: f64, b: f64| ((a - b) / b).abs() < 1e-12;
+        assert!(close(factor("kWh"), 3.6e6));
+        assert!(close(factor("Torr"), 133.32236842105263));
+        assert!(close(factor("keV"), 1.602176634e-16));
+        assert!(close(factor("mrem"), 1e-5));
+        assert!(close(factor("statA"), 0.1 / 299_792_458.0));
+        assert!(close(factor("pc"), 3.085_677_581_491_367e16));
+        assert!(close(factor("sccm"), 7.435_838_900_911_72e-7));
+        assert!(close(unit_def("degF").unwrap().offset, 255.372_222_222_222_2));
+        with(|r| {
+            assert_eq!(r.groups().iter().filter(|g| g.name == "gaussian").count(), 6);
+            assert!(r.groups().iter().all(|g| !g.units.is_empty()));
+        });
+        assert!(qualified_unit("astro", "au").is_some());
+        assert!(qualified_unit("astro", "km").is_none());
+    }
+
+    #[test]
+    fn invalid_definitions() {
+        let kind = |text: &str| load_err(text).kind;
+        assert_eq!(load_err("[a]\nx = y\ny = 2 x\n").line, 2);
+        assert!(matches!(kind("[a]\nx = y\ny = 2 x\n"), DefErrorKind::Cycle(c) if c == "x -> y -> x"));
+        assert_eq!(kind("[a]\nx = 2 m\nx = 3 m\n"), DefErrorKind::Duplicate("x".into()));
+        assert_eq!(kind("[a]\nx = 2 m\n  prefixes: k\nkx = 1\n"), DefErrorKind::Duplicate("kx".into()));
+        assert_eq!(kind("[a]\nkm = 2 m\n"), DefErrorKind::Duplicate("km".into()));
+        assert_eq!(kind("[a]\nx = 2 furlong\n"), DefErrorKind::UnknownUnit("furlong".into()));
+        assert!(matches!(kind("[a: m]\nx = 2 s\n"), DefErrorKind::DimensionMismatch(..)));
+        assert_eq!(
+            kind("[a]\nx = 2 m\n  names: meters\n"),
+            DefErrorKind::NameTaken("meters".into(), "m".into())
+        );
+        assert_eq!(
+            kind("[a]\nx = 2 m\n  names: ft\n"),
+            DefErrorKind::NameIsSymbol("ft".into(), "built-in".into())
+        );
+        assert_eq!(load_err("[a]\nx = 2 m\n[names]\ny: z\n").line, 4);
+        assert_eq!(kind("[a]\nx = codata.e codata.amu\n"), DefErrorKind::Codata);
+        assert_eq!(kind("[auto]\nlength: m, furlong\n"), DefErrorKind::UnknownUnit("furlong".into()));
+        assert!(matches!(kind("[auto]\nlenght: m\n"), DefErrorKind::Syntax(_)));
+    }
+
+    #[test]
+    fn user_definitions() {
+        let furlong = define("furlong = 201.168 m", false).unwrap();
+        assert_eq!((furlong.def.dim, furlong.source), (Dim::len(1), Source::User));
+        let cell = define("mycell = 4.05 ang^3", false).unwrap();
+        assert_eq!(cell.def.dim, Dim::len(3));
+        assert!(matches!(define("mi = 1600 m", false), Err(UserDefError::Builtin(_))));
+        assert!(matches!(define("meters = 2 m", false), Err(UserDefError::Builtin(_))));
+        assert!(matches!(define("1x = 2 m", false), Err(UserDefError::Invalid(_))));
+        assert!(matches!(define("x = 2 y", false), Err(UserDefError::Invalid(_))));
+        define("mi = 1600 m", true).unwrap();
+        assert_eq!(unit_def("mi").unwrap().factor, 1600.0);
+        // `ft` and `km` are built from the metre they would replace
+        for line in ["m = 2 ft", "m = 0.001 km", "m = 3 feet", "m = 1/3 yd^2/ft"] {
+            assert!(
+                matches!(define(line, true), Err(UserDefError::BuiltFrom(..))),
+                "{line}"
+            );
+        }
+        assert_eq!(unit_def("ft").unwrap().factor, 0.3048);
+        define("ft = 0.3 m", true).unwrap();
+        undef("ft").unwrap();
+        assert_eq!(symbol("miles").as_deref(), Some("mi"));
+
+        // dependent definitions follow a redefinition
+        define("race = 2 furlong", false).unwrap();
+        define("furlong = 200 m", false).unwrap();
+        assert_eq!(unit_def("race").unwrap().factor, 400.0);
+        assert!(matches!(undef("furlong"), Err(UserDefError::InUse(..))));
+        undef("race").unwrap();
+        undef("furlong").unwrap();
+        assert!(!is_unit("furlong"));
+        assert!(matches!(undef("m"), Err(UserDefError::NotUser(_))));
+        undef("mi").unwrap();
+        assert_eq!(unit_def("mi").unwrap().factor, 1609.344);
+        with(|r| {
+            let user: Vec<&Group> = r.groups().iter().filter(|g| g.name == "user").collect();
+            assert_eq!(user.len(), 1);
+            assert_eq!(user[0].units, ["mycell"]);
+        });
+    }
+
+    #[test]
+    fn names_and_spellings() {
+        assert_eq!(symbol("meters").as_deref(), Some("m"));
+        assert_eq!(symbol("kilometre").as_deref(), Some("km"));
+        assert_eq!(symbol("micrometers").as_deref(), Some("um"));
+        assert_eq!(symbol("hours").as_deref(), Some("hour"));
+        assert_eq!(symbol("milliseconds").as_deref(), Some("ms"));
+        assert_eq!(symbol("pounds").as_deref(), Some("lb"));
+        assert_eq!(symbol("kilowatt hours").as_deref(), Some("kWh"));
+        assert_eq!(symbol("millibar").as_deref(), Some("mbar"));
+        assert_eq!(symbol("degrees Celsius").as_deref(), Some("degC"));
+        assert_eq!(symbol("kiloseconds"), None);
+    }
+
+    #[test]
+    fn chinese_names() {
+        assert_eq!(symbol("公里").as_deref(), Some("km"));
+        assert_eq!(symbol("千瓦时").as_deref(), Some("kWh"));
+        assert_eq!(symbol("市斤").as_deref(), Some("斤"));
+    }
+
+    #[test]
+    fn symbols_are_never_shadowed() {
+        assert_eq!(symbol("Pa").as_deref(), Some("Pa"));
+        assert_eq!(symbol("mA").as_deref(), Some("mA"));
+        assert_eq!(symbol("MA").as_deref(), Some("MA"));
+        assert_eq!(symbol("Cal").as_deref(), Some("Cal"));
+        assert_eq!(symbol("bar").as_deref(), Some("bar"));
+    }
+
+    #[test]
+    fn case_insensitive_fallback() {
+        assert_eq!(symbol("Meters").as_deref(), Some("m"));
+        assert_eq!(symbol("KILOMETRE").as_deref(), Some("km"));
+        assert_eq!(symbol("degrees celsius").as_deref(), Some("degC"));
+        // symbols keep their case, a prefix letter is never flipped
+        for name in ["Mg", "Mm", "mPa", "Ms", "PM", "KM", "hpa", "pa", "ma", "S"] {
+            assert!(matches!(resolve(name), Resolved::Unknown), "{name}");
+        }
+    }
+
+    #[test]
+    fn search_and_info() {
+        let registry = Registry::builtin();
+        let symbols = |text: &str| -> Vec<String> {
+            registry
+                .search(text)
+                .into_iter()
+                .map(|(unit, _)| unit.symbol.clone())
+                .collect()
+        };
+        assert_eq!(symbols("kwh")[0], "kWh");
+        // a typo, and a word from a description
+        assert!(symbols("kilowat").contains(&String::from("kWh")));
+        assert_eq!(symbols("stilb"), ["sb"]);
+        assert!(symbols("zzzz").is_empty());
+        assert_eq!(registry.unit("km").unwrap().names[0], "kilometer");
+        assert!(registry.unit("km").unwrap().names.contains(&String::from("公里")));
+        assert_eq!(registry.group_of("degC").unwrap().name, "temperature");
+        assert_eq!(edit_distance("kilowat", "kilowatt"), 1);
+    }
+}
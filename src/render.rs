@@ -0,0 +1,277 @@
+use std::fmt::{self, Display};
+
+use crate::convert::ConversionResult;
+use crate::duration;
+use crate::parse::UnitTarget;
+use crate::settings::settings;
+
+/// How numbers in results are written, with `:set notation`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Notation {
+    /// shortest form that reads back as the same f64, e.g. `3.5999999999999996`;
+    /// also accepted as `exact`, which is not `:set exact on` and its fractions
+    Shortest,
+    /// like `Shortest` but never with an exponent, e.g. `0.0000001`
+    Plain,
+    /// `precision` significant digits, with an exponent for very large or
+    /// small values, e.g. `3.60000`
+    Sig,
+    /// `precision` decimals, e.g. `3.600000`
+    Fixed,
+    /// `precision` significant digits with an exponent, e.g. `3.60000e0`
+    Sci,
+    /// like `Sci` with an exponent that is a multiple of 3, e.g. `12.3456e3`
+    Eng,
+}
+
+impl Notation {
+    pub fn from_name(name: &str) -> Option<Notation> {
+        match name {
+            "shortest" | "exact" => Some(Notation::Shortest),
+            "plain" => Some(Notation::Plain),
+            "sig" => Some(Notation::Sig),
+            "fixed" => Some(Notation::Fixed),
+            "sci" => Some(Notation::Sci),
+            "eng" => Some(Notation::Eng),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Notation::Shortest => "shortest",
+            Notation::Plain => "plain",
+            Notation::Sig => "sig",
+            Notation::Fixed => "fixed",
+            Notation::Sci => "sci",
+            Notation::Eng => "eng",
+        }
+    }
+}
+
+impl Display for Notation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Writes `value` in `notation`. `precision` counts significant digits, or
+/// decimals for `Fixed`; it is unused by `Shortest` and `Plain`.
+pub fn format_value(value: f64, notation: Notation, precision: usize) -> String {
+    if !value.is_finite() {
+        return value.to_string();
+    }
+    // significant digits, rounded once so that e.g. 999.96 becomes 1.000e3
+    let digits = precision.max(1);
+    let scientific = || {
+        let s = format!("{:.*e}", digits - 1, value);
+        let (mantissa, exp) = s.split_once('e').unwrap();
+        (mantissa.to_string(), exp.parse::<i32>().unwrap())
+    };
+    match notation {
+        Notation::Shortest => ryu::Buffer::new().format(value).to_string(),
+        Notation::Plain => value.to_string(),
+        Notation::Fixed => format!("{value:.precision$}"),
+        Notation::Sci => {
+            let (mantissa, exp) = scientific();
+            format!("{mantissa}e{exp}")
+        }
+        Notation::Sig => {
+            let (_, exp) = scientific();
+            // as printf's %g: an exponent only outside 1e-4 .. 10^digits
+            if exp < -4 || exp >= digits as i32 {
+                let (mantissa, exp) = scientific();
+                format!("{mantissa}e{exp}")
+            } else {
+                let decimals = (digits as i32 - 1 - exp) as usize;
+                format!("{value:.decimals$}")
+            }
+        }
+        Notation::Eng => {
+            let (mantissa, exp) = scientific();
+            let shift = exp.rem_euclid(3);
+            let (sign, mantissa) = match mantissa.strip_prefix('-') {
+                Some(rest) => ("-", rest),
+                None => ("", mantissa.as_str()),
+            };
+            // move the point `shift` places right, padding with zeros
+            let mut digits: String = mantissa.chars().filter(|c| *c != '.').collect();
+            let int_len = 1 + shift as usize;
+            while digits.len() < int_len {
+                digits.push('0');
+            }
+            let (int, frac) = digits.split_at(int_len);
+            if frac.is_empty() {
+                format!("{sign}{int}e{}", exp - shift)
+            } else {
+                format!("{sign}{int}.{frac}e{}", exp - shift)
+            }
+        }
+    }
+}
+
+/// Writes `value` with the notation and precision of the settings.
+pub fn format_number(value: f64) -> String {
+    let settings = settings();
+    format_value(value, settings.notation, settings.precision)
+}
+
+/// Formats a conversion result as the REPL prints it: the value and the
+/// target symbol, or a duration, then notes and warnings one per line. With
+/// `:set exact on` the value is a fraction when the result is exact.
+pub fn render(result: &ConversionResult) -> String {
+    let exact_mode = settings().exact;
+    let mut out = match (&result.target, result.exact) {
+        (UnitTarget::Duration(style), _) => match duration::format(result.value, *style) {
+            Some(text) => text,
+            None => format!(
+                "{} s\nnote: too long to write as a duration",
+                format_number(result.value)
+            ),
+        },
+        (_, Some(exact)) if exact_mode => format!("{exact} {}", result.symbol),
+        _ => format!("{} {}", format_number(result.value), result.symbol),
+    };
+    if exact_mode && result.exact.is_none() && !matches!(result.target, UnitTarget::Duration(_)) {
+        out.push_str("\nnote: not exact, a measured constant, pi or an equivalence is involved");
+    }
+    for note in &result.notes {
+        out.push_str(&format!("\nnote: {note}"));
+    }
+    for warning in &result.warnings {
+        out.push_str(&format!("\nwarning: {warning}"));
+    }
+    out
+}
+
+/// The result as JSON, for scripts: `{"value", "unit", "dim", "from_factor",
+/// "to_factor", "exact", "notes", "warnings"}`, plus `"text"` for durations,
+/// null when too long to write as one. `"exact"` is a fraction such as
+/// `"1/12"`, or null.
+pub fn render_json(result: &ConversionResult) -> String {
+    result_json(result).to_string()
+}
+
+/// The object `render_json` writes, also embedded by `explain`.
+pub fn result_json(result: &ConversionResult) -> serde_json::Value {
+    let mut json = serde_json::json!({
+        "value": result.value,
+        "unit": result.symbol,
+        "dim": result.dim.to_string(),
+        "from_factor": result.from_factor,
+        "to_factor": result.to_factor,
+        "exact": result.exact.map(|exact| exact.to_string()),
+        "notes": result.notes,
+        "warnings": result.warnings,
+    });
+    if let UnitTarget::Duration(style) = result.target {
+        json["text"] = duration::format(result.value, style).into();
+    }
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convert::convert;
+    use crate::parse::parse_expr;
+
+    fn render_line(line: &str) -> String {
+        render(&convert(&parse_expr(line).unwrap()))
+    }
+
+    #[test]
+    fn values_and_durations() {
+        assert_eq!(render_line("10 km to m"), "10000.0 m");
+        assert_eq!(render_line("93784 s to duration"), "1d 02:03:04");
+        assert_eq!(render_line("1.5 day to iso8601"), "P1DT12H");
+        assert_eq!(render_line("P1Y2M3DT4H5M6S to iso8601"), "P1Y2M3DT4H5M6S");
+        assert_eq!(
+            render_line("1e30 s to duration"),
+            "1e30 s\nnote: too long to write as a duration"
+        );
+    }
+
+    #[test]
+    fn notes_and_warnings() {
+        assert_eq!(
+            render_line("2 cup of flour to g"),
+            "249.9791306859 g\nnote: using density of flour 528.3 kg/m^3"
+        );
+        let out = render_line("1 au to km");
+        assert!(out.lines().nth(1).unwrap().starts_with("warning: "));
+    }
+
+    #[test]
+    fn notations() {
+        let all = |value: f64, precision: usize| -> Vec<String> {
+            [
+                Notation::Shortest,
+                Notation::Plain,
+                Notation::Sig,
+                Notation::Fixed,
+                Notation::Sci,
+                Notation::Eng,
+            ]
+            .into_iter()
+            .map(|notation| format_value(value, notation, precision))
+            .collect()
+        };
+        assert_eq!(
+            all(3.5999999999999996, 4),
+            ["3.5999999999999996", "3.5999999999999996", "3.600", "3.6000", "3.600e0", "3.600e0"]
+        );
+        assert_eq!(
+            all(12_345.678, 3),
+            ["12345.678", "12345.678", "1.23e4", "12345.678", "1.23e4", "12.3e3"]
+        );
+        assert_eq!(
+            all(-1e-7, 2),
+            ["-1e-7", "-0.0000001", "-1.0e-7", "-0.00", "-1.0e-7", "-100e-9"]
+        );
+        assert_eq!(format_value(999.96, Notation::Eng, 4), "1.000e3");
+        assert_eq!(format_value(0.0, Notation::Sig, 3), "0.00");
+        assert_eq!(format_value(1e300, Notation::Plain, 3).len(), 301);
+    }
+
+    #[test]
+    fn notation_settings() {
+        crate::settings::set("notation", "sig").unwrap();
+        crate::settings::set("precision", "4").unwrap();
+        assert_eq!(render_line("1 m/s to km/hour"), "3.600 km/hour");
+        crate::settings::set("notation", "fixed").unwrap();
+        crate::settings::set("precision", "1").unwrap();
+        assert_eq!(render_line("1 ft to cm"), "30.5 cm");
+        // `exact` is kept as another name of `shortest`
+        crate::settings::set("notation", "exact").unwrap();
+        assert_eq!(settings().notation, Notation::Shortest);
+        assert_eq!(render_line("1 ft to cm"), "30.48 cm");
+        assert!(crate::settings::set("notation", "hex").is_err());
+        assert!(crate::settings::set("precision", "-1").is_err());
+    }
+
+    #[test]
+    fn json() {
+        let result = convert(&parse_expr("1 kWh to MJ").unwrap());
+        let json: serde_json::Value = serde_json::from_str(&render_json(&result)).unwrap();
+        assert_eq!(json["value"], 3.6);
+        assert_eq!(json["unit"], "MJ");
+        assert_eq!(json["dim"], result.dim.to_string());
+        assert_eq!(json["from_factor"], 3.6e6);
+        assert_eq!(json["to_factor"], 1e6);
+        assert_eq!(json["exact"], "18/5");
+        assert!(json.get("text").is_none());
+    }
+
+    #[test]
+    fn exact_mode() {
+        crate::settings::set("exact", "on").unwrap();
+        assert_eq!(render_line("1 in to ft"), "1/12 ft");
+        assert_eq!(render_line("100 degC to degF"), "212 degF");
+        assert_eq!(render_line("1 mile to km"), "25146/15625 km");
+        assert_eq!(
+            render_line("1 pc to AU"),
+            "206264.80624709636 AU\nnote: not exact, a measured constant, pi or an equivalence is involved"
+        );
+    }
+}
@@ -1,10 +1,19 @@
-use crate::constants::*;
 use crate::convert::convert;
+use crate::currency;
+use crate::explain::explain;
+use crate::import;
 use crate::parse::ConversionExpr;
-use crate::parse::parse_expr;
+use crate::parse::{parse_expr, parse_quantity, parse_unit};
+use crate::registry::{self, Group, Registry, Resolved, Source};
+use crate::render::{format_number, render};
+use crate::settings::{self, settings};
+use crate::simplify;
+use crate::substances::{define_density, densities};
+use crate::units::Dim;
 use rustyline::DefaultEditor;
 use rustyline::Result;
 use rustyline::error::ReadlineError;
+use std::path::Path;
 
 fn print_help() {
     println!(
@@ -12,47 +21,227 @@ fn print_help() {
 Supported commands:
 exit|quit:   exit the repl
 help:        print this help message
-list:        list the supported basic units
-<expr>:      convert the expression
+list:        list the units by group, `list <group>` one group, `list user` the ones added with def
+info:        `info <unit>` shows the name, group, dimension, factor, definition and aliases of a unit
+search:      `search <text>` finds units by symbol, name or description
+def:         `def furlong = 201.168 m` adds a unit, `def!` replaces a built-in one
+undef:       `undef <unit>` removes a unit added with def
+import:      `import <file>` adds the units of a GNU units or pint definitions file
+explain:     `explain 1 kWh/day to W` shows the factors, dimensions and ratio behind a result
+simplify:    `simplify kg*m/s^2` finds the shortest named unit, as `to simplest` does
+density:     list substance densities, `density <name> <value> <unit>` adds one
+rates:       show the exchange rates, `rates <file>` loads a CSV or JSON file
+:set:        show the settings, `:set <key> <value>` changes one
+             codata <2006|2014|2018|2022>
+             system <si|natural|planck|gaussian>
+             strict <on|off>
+             efficacy <on|off|lm/W>   link W and lm, `on` is 683 lm/W
+             notation <shortest|plain|sig|fixed|sci|eng>
+             precision <n>            significant digits, decimals for fixed
+             exact <on|off>           fractions such as 1/12 when the result is exact, whatever the notation
+             profile <none|chemistry> preferences of `to auto`
+<expr>:      convert the expression, `to auto` or no target picks a unit
 
 Examples:
-10 km to m"#
+10 km to m
+1 J to gaussian
+PT90M to hour
+250 mL of ethanol to g
+1 mg/dL glucose to mmol/L
+1200 USD/month to EUR/year
+density syrup 1.33 g/mL
+100000 s to duration
+:set codata 2018"#
     );
 }
 
 pub fn print_units_grouped(arg: Option<&str>) {
-    fn print_group(title: &str, units: &[(&'static str, f64)]) {
+    fn print_group(registry: &Registry, group: &Group) {
         let mut buffer = ryu::Buffer::new();
-        println!("[{}] ", title);
-        for &(name, factor) in units {
-            println!("{} {}", name, buffer.format(factor));
+        let codata = settings().codata;
+        let mut header = format!("[{}]", group.name);
+        if let Some(dim) = group.dim {
+            header.push_str(&format!(" {dim}"));
         }
-    }
-    match arg {
-        None => {
-            for (&k, &v) in UNIT_GROUP_MAP.iter() {
-                print_group(k, v);
+        if let Some(kind) = group.kind {
+            header.push_str(&format!(" kind: {kind}"));
+        }
+        println!("{header}");
+        let mut units: Vec<_> = group
+            .units
+            .iter()
+            .filter_map(|symbol| registry.unit(symbol))
+            .collect();
+        units.sort_by(|a, b| {
+            let (fa, fb) = (a.def.si_factor(codata), b.def.si_factor(codata));
+            fa.total_cmp(&fb).then_with(|| a.symbol.cmp(&b.symbol))
+        });
+        for unit in units {
+            print!(
+                "{} {}",
+                unit.symbol,
+                buffer.format(unit.def.si_factor(codata))
+            );
+            if unit.def.offset != 0.0 {
+                print!(" zero: {} K", buffer.format(unit.def.offset));
+            }
+            if unit.source == Source::User {
+                print!(" = {}", unit.definition);
+            }
+            if let Some(description) = &unit.description {
+                print!(" ({description})");
             }
+            println!();
         }
-        Some(t) => {
+    }
+    registry::with(|registry| {
+        // a short name stands for every group of that name
+        let key = arg.map(|t| {
             let key = t.trim().to_lowercase();
-            match UNIT_GROUP_MAP.get(key.as_str()) {
-                None => {
-                    println!("Unknown group: {key}");
-                }
-                Some(&units) => {
-                    print_group(&key, units);
-                }
+            registry
+                .groups()
+                .iter()
+                .find(|group| group.short.as_ref() == Some(&key))
+                .map_or(key, |group| group.name.clone())
+        });
+        // by name, groups of the same name keep their order
+        let mut groups: Vec<&Group> = registry.groups().iter().collect();
+        groups.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut found = false;
+        for group in groups {
+            if let Some(key) = &key
+                && group.name != *key
+            {
+                continue;
             }
+            print_group(registry, group);
+            found = true;
+        }
+        if let Some(key) = key
+            && !found
+        {
+            println!("Unknown group: {key}");
+        }
+    });
+}
+
+fn print_unit_info(name: &str) {
+    let symbol = match registry::resolve(name) {
+        Resolved::Unit(symbol) => symbol,
+        Resolved::Ambiguous(symbols) => {
+            println!("{name} may be {}", symbols.join(" or "));
+            return;
+        }
+        Resolved::Unknown => {
+            println!("Unknown unit: {name}");
+            return;
+        }
+    };
+    registry::with(|registry| {
+        let unit = registry.unit(&symbol).unwrap();
+        let mut buffer = ryu::Buffer::new();
+        let full_name = unit
+            .names
+            .iter()
+            .find(|name| name.is_ascii())
+            .or(unit.names.first());
+        match full_name {
+            Some(full_name) => println!("{} ({full_name})", unit.symbol),
+            None => println!("{}", unit.symbol),
+        }
+        if let Some(description) = &unit.description {
+            println!("  {description}");
+        }
+        if let Some(group) = registry.group_of(&unit.symbol) {
+            match group.kind {
+                Some(kind) => println!("group:      {}, kind: {kind}", group.name),
+                None => println!("group:      {}", group.name),
+            }
+        }
+        println!("dimension:  {}", unit.def.dim);
+        println!(
+            "SI factor:  {}",
+            buffer.format(unit.def.si_factor(settings().codata))
+        );
+        if unit.def.offset != 0.0 {
+            println!("zero:       {} K", buffer.format(unit.def.offset));
+        }
+        println!("definition: {} = {}", unit.symbol, unit.definition);
+        if !unit.names.is_empty() {
+            println!("aliases:    {}", unit.names.join(", "));
+        }
+        println!("source:     {}", unit.source);
+    });
+}
+
+fn print_search(text: &str) {
+    const LIMIT: usize = 20;
+    registry::with(|registry| {
+        let found = registry.search(text);
+        if found.is_empty() {
+            println!("No unit matches {text}");
+        }
+        for (unit, matched) in found.iter().take(LIMIT) {
+            let group = registry
+                .group_of(&unit.symbol)
+                .map_or("", |g| g.name.as_str());
+            if *matched == unit.symbol {
+                println!("{} [{group}]", unit.symbol);
+            } else {
+                println!("{} [{group}] {matched}", unit.symbol);
+            }
+        }
+        if found.len() > LIMIT {
+            println!("... {} more", found.len() - LIMIT);
+        }
+    });
+}
+
+fn print_simplest(text: &str) {
+    let unit = match parse_unit(text) {
+        Ok(unit) => unit,
+        Err(e) => {
+            println!("{}", e.kind);
+            return;
+        }
+    };
+    match simplify::simplest_unit(&unit) {
+        Some(simplest) if simplest.symbol == unit.symbol => {
+            println!("{} is as simple as it gets", unit.symbol);
+        }
+        Some(simplest) if simplest.factor == unit.factor => {
+            println!("{} = {}", unit.symbol, simplest.symbol);
         }
+        Some(simplest) => println!(
+            "{} = {} {}",
+            unit.symbol,
+            format_number(unit.factor / simplest.factor),
+            simplest.symbol
+        ),
+        None => println!("{} has no simpler form", unit.symbol),
     }
 }
 
+const DENSITY: Dim = Dim {
+    len: -3,
+    ..Dim::mass(1)
+};
+
 enum ReplCmd<'a> {
     Help,
     Exit,
     List(Option<&'a str>),
-    Expr(ConversionExpr),
+    Info(&'a str),
+    Search(&'a str),
+    Set(Option<(&'a str, &'a str)>),
+    Density(Option<(&'a str, &'a str)>),
+    Rates(Option<&'a str>),
+    Def(&'a str, bool),
+    Undef(&'a str),
+    Import(&'a str),
+    Explain(Box<ConversionExpr>),
+    Simplify(&'a str),
+    Expr(Box<ConversionExpr>),
     Invalid(String),
     Empty,
 }
@@ -73,9 +262,90 @@ fn parse_repl(line: &str) -> ReplCmd<'_> {
             }
             ReplCmd::List(arg)
         }
+        "info" => {
+            let rest = line[head.len()..].trim();
+            if rest.is_empty() {
+                return ReplCmd::Invalid(String::from("usage: info <unit>"));
+            }
+            ReplCmd::Info(rest)
+        }
+        "search" => {
+            let rest = line[head.len()..].trim();
+            if rest.is_empty() {
+                return ReplCmd::Invalid(String::from("usage: search <text>"));
+            }
+            ReplCmd::Search(rest)
+        }
+        ":set" => {
+            let args: Vec<&str> = it.collect();
+            match args[..] {
+                [] => ReplCmd::Set(None),
+                [key, value] => ReplCmd::Set(Some((key, value))),
+                _ => ReplCmd::Invalid(String::from("usage: :set [<key> <value>]")),
+            }
+        }
+        "density" => match it.next() {
+            None => ReplCmd::Density(None),
+            Some(name) => {
+                let rest = line[head.len()..].trim_start()[name.len()..].trim();
+                let valid = name.starts_with(|c: char| c.is_ascii_alphabetic())
+                    && name.chars().all(|c| c.is_alphanumeric() || c == '_');
+                if rest.is_empty() || !valid {
+                    return ReplCmd::Invalid(String::from(
+                        "usage: density [<name> <value> <unit>]",
+                    ));
+                }
+                ReplCmd::Density(Some((name, rest)))
+            }
+        },
+        "rates" => {
+            let rest = line[head.len()..].trim();
+            ReplCmd::Rates((!rest.is_empty()).then_some(rest))
+        }
+        "def" | "def!" => {
+            let rest = line[head.len()..].trim();
+            if rest.is_empty() {
+                return ReplCmd::Invalid(String::from("usage: def <name> = <expression>"));
+            }
+            ReplCmd::Def(rest, head == "def!")
+        }
+        "undef" => match (it.next(), it.next()) {
+            (Some(name), None) => ReplCmd::Undef(name),
+            _ => ReplCmd::Invalid(String::from("usage: undef <name>")),
+        },
+        "import" => {
+            let rest = line[head.len()..].trim();
+            if rest.is_empty() {
+                return ReplCmd::Invalid(String::from("usage: import <file>"));
+            }
+            ReplCmd::Import(rest)
+        }
+        "explain" => {
+            let rest = line[head.len()..].trim_start();
+            if rest.is_empty() {
+                return ReplCmd::Invalid(String::from("usage: explain <expr>"));
+            }
+            match parse_expr(rest) {
+                Ok(expr) => ReplCmd::Explain(Box::new(expr)),
+                // point below the expression, past `explain `
+                Err(e) if e.pos.is_some() => ReplCmd::Invalid(format!(
+                    "{}{}",
+                    " ".repeat(line.len() - rest.len()),
+                    e.format_repl()
+                )),
+                Err(e) => ReplCmd::Invalid(e.format_repl()),
+            }
+        }
+        "simplify" => {
+            let rest = line[head.len()..].trim();
+            if rest.is_empty() {
+                return ReplCmd::Invalid(String::from("usage: simplify <unit>"));
+            }
+            ReplCmd::Simplify(rest)
+        }
         "exit" | "quit" => ReplCmd::Exit,
         _ => match parse_expr(line) {
-            Ok(expr) => ReplCmd::Expr(expr),
+            Ok(expr) => ReplCmd::Expr(Box::new(expr)),
             Err(e) => ReplCmd::Invalid(e.format_repl()),
         },
     }
@@ -100,11 +370,81 @@ pub fn run_repl() -> Result<()> {
                     List(arg) => {
                         print_units_grouped(arg);
                     }
+                    Info(name) => {
+                        print_unit_info(name);
+                    }
+                    Search(text) => {
+                        print_search(text);
+                    }
+                    Set(None) => {
+                        print!("{}", settings());
+                    }
+                    Set(Some((key, value))) => {
+                        if let Err(e) = settings::set(key, value) {
+                            println!("{}", e);
+                        }
+                    }
+                    Density(None) => {
+                        let mut buffer = ryu::Buffer::new();
+                        for (name, d) in densities() {
+                            println!("{} {} kg/m^3", name, buffer.format(d));
+                        }
+                    }
+                    Density(Some((name, value))) => match parse_quantity(value) {
+                        Ok((v, unit)) if unit.dim == DENSITY => {
+                            define_density(name, v * unit.factor);
+                        }
+                        Ok((_, unit)) => {
+                            println!("A density must be {}, got {}", DENSITY, unit.dim);
+                        }
+                        Err(e) => {
+                            println!("{}", e.kind);
+                        }
+                    },
+                    Rates(None) => match currency::rates() {
+                        Some(rates) => {
+                            println!("{} based, {}", rates.base, rates.date);
+                            let mut buffer = ryu::Buffer::new();
+                            for (code, rate) in &rates.rates {
+                                println!("{} {}", code, buffer.format(*rate));
+                            }
+                        }
+                        None => println!("No exchange rates loaded"),
+                    },
+                    Rates(Some(path)) => {
+                        if let Err(e) = currency::load(Path::new(path)) {
+                            println!("{}", e);
+                        }
+                    }
+                    Def(def, force) => match registry::define(def, force) {
+                        Ok(unit) => {
+                            println!("{} = {} ({})", unit.symbol, unit.definition, unit.def.dim);
+                            if let Err(e) = registry::save_user() {
+                                println!("{}", e);
+                            }
+                        }
+                        Err(e) => println!("{}", e),
+                    },
+                    Undef(name) => {
+                        if let Err(e) = registry::undef(name).and_then(|_| registry::save_user()) {
+                            println!("{}", e);
+                        }
+                    }
+                    Import(path) => match import::import(Path::new(path)) {
+                        Ok(report) => println!("{}", report),
+                        Err(e) => println!("{}", e),
+                    },
                     Exit => {
                         break;
                     }
+                    Explain(expr) => {
+                        println!("{}", explain(&expr));
+                    }
+                    Simplify(unit) => {
+                        print_simplest(unit);
+                    }
                     Expr(expr) => {
-                        println!("{}", convert(&expr));
+                        println!("{}", render(&convert(&expr)));
                     }
                     Invalid(msg) => {
                         println!("{}", msg);
@@ -0,0 +1,178 @@
+use std::cell::RefCell;
+use std::env;
+use std::fmt::{self, Display};
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+use crate::constants::{CODATA_2022, CODATA_SETS, Codata, LUMINOUS_EFFICACY_555NM, codata_by_year};
+use crate::registry;
+use crate::render::Notation;
+use crate::systems::UnitSystem;
+
+#[derive(Debug, Error)]
+pub enum SettingsError {
+    #[error("Unknown setting: {0}")]
+    UnknownKey(String),
+
+    #[error("Invalid value for {key}: {value} (expected {expected})")]
+    InvalidValue {
+        key: String,
+        value: String,
+        expected: String,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub codata: &'static Codata,
+    /// decides which dimensions and electromagnetic units are interchangeable
+    pub system: UnitSystem,
+    /// reject conversions between different quantity kinds instead of warning
+    pub strict: bool,
+    /// lm/W linking radiant and luminous quantities, off unless asked for
+    pub efficacy: Option<f64>,
+    /// how results are written
+    pub notation: Notation,
+    /// significant digits, or decimals for `fixed`
+    pub precision: usize,
+    /// print exact results as fractions, e.g. `1/12 ft`
+    pub exact: bool,
+    /// the `[auto <profile>]` preferences `to auto` follows, e.g. `chemistry`
+    pub profile: Option<String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            codata: &CODATA_2022,
+            system: UnitSystem::Si,
+            strict: false,
+            efficacy: None,
+            notation: Notation::Shortest,
+            precision: 6,
+            exact: false,
+            profile: None,
+        }
+    }
+}
+
+impl Display for Settings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "codata = {}", self.codata.year)?;
+        writeln!(f, "system = {}", self.system)?;
+        writeln!(f, "strict = {}", if self.strict { "on" } else { "off" })?;
+        match self.efficacy {
+            Some(efficacy) => writeln!(f, "efficacy = {efficacy} lm/W")?,
+            None => writeln!(f, "efficacy = off")?,
+        }
+        writeln!(f, "notation = {}", self.notation)?;
+        writeln!(f, "precision = {}", self.precision)?;
+        writeln!(f, "exact = {}", if self.exact { "on" } else { "off" })?;
+        writeln!(f, "profile = {}", self.profile.as_deref().unwrap_or("none"))
+    }
+}
+
+// The repl is single threaded; keeping the settings per thread also lets
+// tests change them without affecting each other.
+thread_local! {
+    static SETTINGS: RefCell<Settings> = RefCell::new(Settings::default());
+}
+
+pub fn settings() -> Settings {
+    SETTINGS.with(|s| s.borrow().clone())
+}
+
+/// Applies `key = value`, as given by `:set key value` or a CLI flag.
+pub fn set(key: &str, value: &str) -> Result<(), SettingsError> {
+    let invalid = |expected: String| SettingsError::InvalidValue {
+        key: key.to_string(),
+        value: value.to_string(),
+        expected,
+    };
+    match key {
+        "codata" => {
+            let codata = value.parse().ok().and_then(codata_by_year).ok_or_else(|| {
+                let years: Vec<String> = CODATA_SETS.iter().map(|c| c.year.to_string()).collect();
+                invalid(years.join("|"))
+            })?;
+            SETTINGS.with(|s| s.borrow_mut().codata = codata);
+            Ok(())
+        }
+        "system" => {
+            let system = UnitSystem::from_name(value)
+                .ok_or_else(|| invalid(String::from("si|natural|planck|gaussian")))?;
+            SETTINGS.with(|s| s.borrow_mut().system = system);
+            Ok(())
+        }
+        "strict" => {
+            let strict = match value {
+                "on" => true,
+                "off" => false,
+                _ => return Err(invalid(String::from("on|off"))),
+            };
+            SETTINGS.with(|s| s.borrow_mut().strict = strict);
+            Ok(())
+        }
+        "efficacy" => {
+            let efficacy = match value {
+                "on" => Some(LUMINOUS_EFFICACY_555NM),
+                "off" => None,
+                _ => match value.parse::<f64>() {
+                    Ok(v) if v > 0.0 => Some(v),
+                    _ => return Err(invalid(String::from("on|off|<lm/W>"))),
+                },
+            };
+            SETTINGS.with(|s| s.borrow_mut().efficacy = efficacy);
+            Ok(())
+        }
+        "notation" => {
+            let notation = Notation::from_name(value)
+                .ok_or_else(|| invalid(String::from("shortest|plain|sig|fixed|sci|eng")))?;
+            SETTINGS.with(|s| s.borrow_mut().notation = notation);
+            Ok(())
+        }
+        "precision" => {
+            let precision = match value.parse::<usize>() {
+                Ok(n) if n <= 17 => n,
+                _ => return Err(invalid(String::from("0..17"))),
+            };
+            SETTINGS.with(|s| s.borrow_mut().precision = precision);
+            Ok(())
+        }
+        "exact" => {
+            let exact = match value {
+                "on" => true,
+                "off" => false,
+                _ => return Err(invalid(String::from("on|off"))),
+            };
+            SETTINGS.with(|s| s.borrow_mut().exact = exact);
+            Ok(())
+        }
+        "profile" => {
+            let profiles = registry::with(|r| {
+                r.profiles()
+                    .into_iter()
+                    .map(String::from)
+                    .collect::<Vec<_>>()
+            });
+            let profile = match value {
+                "none" => None,
+                _ if profiles.iter().any(|p| p == value) => Some(value.to_string()),
+                _ => return Err(invalid(format!("none|{}", profiles.join("|")))),
+            };
+            SETTINGS.with(|s| s.borrow_mut().profile = profile);
+            Ok(())
+        }
+        _ => Err(SettingsError::UnknownKey(key.to_string())),
+    }
+}
+
+/// `$XDG_CONFIG_HOME/unitrepl`, falling back to `~/.config/unitrepl`.
+pub fn config_dir() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("unitrepl"))
+}
@@ -0,0 +1,256 @@
+use crate::constants::Codata;
+use crate::parse::{UnitExpr, parse_unit};
+use crate::registry::{self, Registry, Unit};
+use crate::settings::settings;
+use crate::units::{Dim, QuantityKind};
+
+// powers tried for each unit, lowest first
+const POWERS: [i32; 6] = [1, -1, 2, -2, 3, -3];
+
+fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() <= 1e-12 * a.abs().max(b.abs())
+}
+
+fn pow(dim: Dim, k: i32) -> Dim {
+    let mut dim = dim;
+    dim.pow(k);
+    dim
+}
+
+fn mul(a: Dim, b: Dim) -> Dim {
+    let mut dim = a;
+    dim.mul(b);
+    dim
+}
+
+const BASE: [&str; 7] = ["kg", "m", "s", "K", "A", "cd", "mol"];
+
+fn is_base(unit: &Unit) -> bool {
+    BASE.contains(&unit.symbol.as_str())
+}
+
+// units with their powers, and the catalog position of each unit
+struct Candidate<'r> {
+    parts: Vec<(&'r Unit, i32, usize)>,
+    factor: f64,
+}
+
+impl Candidate<'_> {
+    // as `parse_unit_expr` composes it
+    fn kind(&self) -> Option<QuantityKind> {
+        self.parts
+            .iter()
+            .map(|(unit, k, _)| QuantityKind::pow(unit.def.kind, *k))
+            .reduce(QuantityKind::mul)
+            .flatten()
+    }
+
+    /// The order of `simplest`, smallest first.
+    fn rank(
+        &self,
+        factor: f64,
+        kind: Option<QuantityKind>,
+    ) -> (usize, bool, bool, bool, usize, i32, usize, Vec<usize>) {
+        let inverse = self.parts.iter().all(|(_, k, _)| *k < 0);
+        let derived = self
+            .parts
+            .iter()
+            .filter(|(unit, _, _)| !is_base(unit))
+            .count();
+        let powers = self.parts.iter().map(|(_, k, _)| k.abs()).sum();
+        let negatives = self.parts.iter().filter(|(_, k, _)| *k < 0).count();
+        let order = self.parts.iter().map(|(_, _, i)| *i).collect();
+        (
+            self.parts.len(),
+            !close(self.factor, factor),
+            kind.is_some() && self.kind() != kind,
+            inverse,
+            derived,
+            powers,
+            negatives,
+            order,
+        )
+    }
+
+    fn symbol(&self) -> String {
+        // named units before base units, as in `N*s` and `W/m^2`
+        let mut parts = self.parts.clone();
+        parts.sort_by_key(|(unit, _, i)| (is_base(unit), *i));
+        let term = |unit: &Unit, k: i32| match k {
+            1 => unit.symbol.clone(),
+            k => format!("{}^{k}", unit.symbol),
+        };
+        let num: Vec<String> = parts
+            .iter()
+            .filter(|(_, k, _)| *k > 0)
+            .map(|(unit, k, _)| term(unit, *k))
+            .collect();
+        let den: Vec<String> = parts
+            .iter()
+            .filter(|(_, k, _)| *k < 0)
+            .map(|(unit, k, _)| term(unit, -k))
+            .collect();
+        match (num.len(), den.len()) {
+            (_, 0) => num.join("*"),
+            (0, _) => parts
+                .iter()
+                .map(|(unit, k, _)| term(unit, *k))
+                .collect::<Vec<_>>()
+                .join("*"),
+            (_, 1) => format!("{}/{}", num.join("*"), den[0]),
+            _ => format!("{}/({})", num.join("*"), den.join("*")),
+        }
+    }
+}
+
+/// The shortest expression for `dim` in named units of the catalog, e.g.
+/// `V` for `kg*m^2/(s^3*A)` and `W` for `J/s`. `factor` is the SI value of
+/// the unit being simplified: a unit with the same factor keeps the value
+/// as it is (`kW` for `kJ/s`), other candidates must be coherent SI units
+/// with a factor of 1. Of these the first in this order wins:
+///
+/// 1. the fewest units, `m^3` counting as one;
+/// 2. the same factor as the unit given;
+/// 3. the same kind as the unit given, `Gy/s` before `m^2/s^3`;
+/// 4. not only a denominator, `Hz` before `s^-1`;
+/// 5. the fewest units that are not SI base units, `m/s` before `m*Hz`;
+/// 6. the lowest powers;
+/// 7. the fewest units in the denominator;
+/// 8. the units listed first in `units.def`, where SI units come first.
+///
+/// A candidate must be of `kind`, or of no kind at all: `N*m` stays torque
+/// rather than becoming `J`, and `lm` is not `cd`. Without a kind, units of
+/// guarded kinds such as `Bq` and `Gy` are never picked. Temperature scales
+/// are never picked either. `None` for dimensionless and currency
+/// dimensions.
+pub fn simplest(
+    registry: &Registry,
+    dim: Dim,
+    factor: f64,
+    kind: Option<QuantityKind>,
+    codata: &Codata,
+) -> Option<String> {
+    if dim == Dim::zero() || dim.currency != 0 {
+        return None;
+    }
+    let mut pool: Vec<(&Unit, usize)> = Vec::new();
+    for symbol in registry.groups().iter().flat_map(|group| &group.units) {
+        if let Some(unit) = registry.unit(symbol)
+            && unit.def.offset == 0.0
+            && unit.def.dim != Dim::zero()
+            && !pool.iter().any(|(u, _)| u.symbol == unit.symbol)
+        {
+            pool.push((unit, pool.len()));
+        }
+    }
+    let si_factor = |unit: &Unit| unit.def.si_factor(codata);
+
+    let mut candidates: Vec<Candidate> = Vec::new();
+    for &(unit, i) in &pool {
+        for k in POWERS {
+            let f = si_factor(unit).powi(k);
+            if pow(unit.def.dim, k) == dim && (close(f, factor) || close(f, 1.0)) {
+                candidates.push(Candidate {
+                    parts: vec![(unit, k, i)],
+                    factor: f,
+                });
+            }
+        }
+    }
+    // two coherent units, such as `J/kg` or `Wb/m`
+    let coherent: Vec<(&Unit, usize)> = pool
+        .iter()
+        .copied()
+        .filter(|(unit, _)| close(si_factor(unit), 1.0))
+        .collect();
+    for (n, &(a, i)) in coherent.iter().enumerate() {
+        for &(b, j) in &coherent[n + 1..] {
+            for ka in POWERS {
+                for kb in POWERS {
+                    if mul(pow(a.def.dim, ka), pow(b.def.dim, kb)) == dim {
+                        candidates.push(Candidate {
+                            parts: vec![(a, ka, i), (b, kb, j)],
+                            factor: 1.0,
+                        });
+                    }
+                }
+            }
+        }
+    }
+    // the SI base units always do
+    if candidates.is_empty() {
+        let base = [
+            ("kg", dim.mass),
+            ("m", dim.len),
+            ("s", dim.time),
+            ("K", dim.temp),
+            ("A", dim.curr),
+            ("cd", dim.lum),
+            ("mol", dim.amount),
+        ];
+        let parts = base
+            .iter()
+            .filter(|(_, k)| *k != 0)
+            .map(|&(symbol, k)| {
+                let unit = registry.unit(symbol)?;
+                let i = pool.iter().find(|(u, _)| u.symbol == symbol)?.1;
+                Some((unit, k, i))
+            })
+            .collect::<Option<Vec<_>>>()?;
+        candidates.push(Candidate { parts, factor: 1.0 });
+    }
+    let fits = |candidate: &Candidate| match (candidate.kind(), kind) {
+        (None, _) => true,
+        (Some(a), Some(b)) => a == b,
+        (Some(a), None) => !a.is_guarded(),
+    };
+    candidates
+        .iter()
+        .filter(|candidate| fits(candidate))
+        .min_by_key(|candidate| candidate.rank(factor, kind))
+        .map(Candidate::symbol)
+}
+
+/// `simplest` for a parsed unit, as the unit expression it reads back as.
+pub fn simplest_unit(unit: &UnitExpr) -> Option<UnitExpr> {
+    let codata = settings().codata;
+    let symbol = registry::with(|r| simplest(r, unit.dim, unit.factor, unit.kind, codata))?;
+    parse_unit(&symbol).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn simplest_of(unit: &str) -> Option<String> {
+        simplest_unit(&parse_unit(unit).unwrap()).map(|unit| unit.symbol)
+    }
+
+    #[test]
+    fn named_units() {
+        assert_eq!(simplest_of("kg*m^2/(s^3*A)").as_deref(), Some("V"));
+        assert_eq!(simplest_of("J/s").as_deref(), Some("W"));
+        assert_eq!(simplest_of("kg*m/s^2").as_deref(), Some("N"));
+        assert_eq!(simplest_of("kg*m^2/s^2").as_deref(), Some("J"));
+        assert_eq!(simplest_of("kJ/s").as_deref(), Some("kW"));
+        assert_eq!(simplest_of("1/s").as_deref(), Some("Hz"));
+        assert_eq!(simplest_of("A*s").as_deref(), Some("C"));
+        assert_eq!(simplest_of("km/hour").as_deref(), Some("m/s"));
+        assert_eq!(simplest_of("kg*m/s").as_deref(), Some("N*s"));
+        assert_eq!(simplest_of("m^3").as_deref(), Some("m^3"));
+        assert_eq!(simplest_of("kWh").as_deref(), Some("kWh"));
+        assert_eq!(simplest_of("%"), None);
+    }
+
+    #[test]
+    fn kinds_are_kept() {
+        assert_eq!(simplest_of("Bq").as_deref(), Some("Bq"));
+        assert_eq!(simplest_of("kBq").as_deref(), Some("kBq"));
+        assert_eq!(simplest_of("mGy/hour").as_deref(), Some("Gy/s"));
+        assert_eq!(simplest_of("Sv").as_deref(), Some("Sv"));
+        assert_eq!(simplest_of("N*m").as_deref(), Some("N*m"));
+        assert_eq!(simplest_of("lm").as_deref(), Some("lm"));
+        // without a kind, never a guarded one
+        assert_eq!(simplest_of("m^2/s^2").as_deref(), Some("m^2/s^2"));
+    }
+}
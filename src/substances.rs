@@ -0,0 +1,103 @@
+use std::cell::RefCell;
+
+use crate::chemistry::molar_mass;
+use crate::equivalence::Equivalence;
+use crate::units::Dim;
+
+// Densities in kg/m^3 at 20 degC and 1 atm. Kitchen ingredients are loose
+// and use the common weight of one US cup (236.6 mL).
+pub static DENSITIES: &[(&str, f64)] = &[
+    ("water", 998.2),
+    ("seawater", 1025.0),
+    ("ethanol", 789.3),
+    ("methanol", 791.4),
+    ("isopropanol", 786.0),
+    ("acetone", 784.5),
+    ("glycerol", 1261.0),
+    ("mercury", 13_546.0),
+    ("gasoline", 745.0),
+    ("diesel", 832.0),
+    ("air", 1.204),
+    ("milk", 1030.0),
+    ("olive_oil", 911.0),
+    ("honey", 1437.0),      // 340 g/cup
+    ("flour", 528.3),       // 125 g/cup
+    ("sugar", 845.4),       // 200 g/cup
+    ("brown_sugar", 929.9), // 220 g/cup
+    ("butter", 959.5),      // 227 g/cup
+    ("salt", 1234.2),       // 292 g/cup
+    ("rice", 781.9),        // 185 g/cup
+];
+
+thread_local! {
+    // added in the repl with `density <name> <value> <unit>`
+    static USER_DENSITIES: RefCell<Vec<(String, f64)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Density of a substance in kg/m^3, user definitions first.
+pub fn density(name: &str) -> Option<f64> {
+    let user = USER_DENSITIES.with(|u| u.borrow().iter().find(|(n, _)| n == name).map(|&(_, d)| d));
+    user.or_else(|| DENSITIES.iter().find(|&&(n, _)| n == name).map(|&(_, d)| d))
+}
+
+pub fn define_density(name: &str, kg_per_m3: f64) {
+    USER_DENSITIES.with(|u| {
+        let mut u = u.borrow_mut();
+        u.retain(|(n, _)| n != name);
+        u.push((name.to_string(), kg_per_m3));
+    });
+}
+
+/// All known densities, built-in ones first.
+pub fn densities() -> Vec<(String, f64)> {
+    let mut list: Vec<(String, f64)> = DENSITIES.iter().map(|&(n, d)| (n.to_string(), d)).collect();
+    USER_DENSITIES.with(|u| {
+        for (name, d) in u.borrow().iter() {
+            list.retain(|(n, _)| n != name);
+            list.push((name.clone(), *d));
+        }
+    });
+    list
+}
+
+/// Whether `name` has a density or a molar mass.
+pub fn is_known(name: &str) -> bool {
+    density(name).is_some() || molar_mass(name).is_some()
+}
+
+/// The links a substance gives between mass and volume (its density),
+/// mass and amount (its molar mass), and volume and amount when both
+/// are known.
+pub fn equivalences(name: &str) -> Vec<Equivalence> {
+    let density = density(name);
+    let molar_mass = molar_mass(name);
+    let mut list = Vec::new();
+    if let Some(d) = density {
+        list.push(Equivalence {
+            description: format!("density of {name} {d} kg/m^3"),
+            from: Dim::len(3),
+            to: Dim::mass(1),
+            factor: d,
+            to_kinds: None,
+        });
+    }
+    if let Some(m) = molar_mass {
+        list.push(Equivalence {
+            description: format!("molar mass of {name} {m:.3} g/mol"),
+            from: Dim::amount(1),
+            to: Dim::mass(1),
+            factor: m / 1000.0,
+            to_kinds: None,
+        });
+    }
+    if let (Some(d), Some(m)) = (density, molar_mass) {
+        list.push(Equivalence {
+            description: format!("density of {name} {d} kg/m^3 and molar mass {m:.3} g/mol"),
+            from: Dim::len(3),
+            to: Dim::amount(1),
+            factor: d / (m / 1000.0),
+            to_kinds: None,
+        });
+    }
+    list
+}
@@ -0,0 +1,169 @@
+use std::fmt::{self, Display};
+
+use crate::constants::{Codata, SPEED_OF_LIGHT};
+use crate::currency;
+use crate::units::Dim;
+
+/// A system of units that a result can be expressed in, or that decides
+/// which dimensions are interchangeable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnitSystem {
+    Si,
+    /// hbar = c = k_B = 1, everything is a power of eV
+    Natural,
+    /// hbar = c = G = k_B = 1, everything is a pure number
+    Planck,
+    /// CGS with the Gaussian electromagnetic units. Dimensions stay those
+    /// of SI, with the ampere scaled to the statampere, so charge is written
+    /// in `statC` rather than `g^1/2*cm^3/2*s^-1`. That holds for electric
+    /// quantities only, magnetic ones have no mapping.
+    Gaussian,
+}
+
+/// B, magnetic flux and H, which Gaussian units set apart from SI by a
+/// factor of c as well: `1 T` is `1e4 gauss`, not a rescaled `kg/(s^2*A)`.
+const MAGNETIC: [Dim; 3] = [
+    Dim::new(0, 1, -2, 0, -1),
+    Dim::new(2, 1, -2, 0, -1),
+    Dim::new(-1, 0, 0, 0, 1),
+];
+
+fn join_symbols(parts: &[(&str, i32)]) -> String {
+    let parts: Vec<String> = parts
+        .iter()
+        .filter(|&&(_, exp)| exp != 0)
+        .map(|&(sym, exp)| {
+            if exp == 1 {
+                sym.to_string()
+            } else {
+                format!("{sym}^{exp}")
+            }
+        })
+        .collect();
+    if parts.is_empty() {
+        String::from("1")
+    } else {
+        parts.join("*")
+    }
+}
+
+impl UnitSystem {
+    pub fn from_name(name: &str) -> Option<UnitSystem> {
+        match name {
+            "si" => Some(UnitSystem::Si),
+            "natural" => Some(UnitSystem::Natural),
+            "planck" => Some(UnitSystem::Planck),
+            "gaussian" => Some(UnitSystem::Gaussian),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            UnitSystem::Si => "si",
+            UnitSystem::Natural => "natural",
+            UnitSystem::Planck => "planck",
+            UnitSystem::Gaussian => "gaussian",
+        }
+    }
+
+    /// Exponent of eV that `dim` becomes when hbar = c = k_B = 1.
+    fn energy_power(dim: Dim) -> i32 {
+        dim.mass + dim.temp - dim.len - dim.time
+    }
+
+    /// Number of system units in one SI unit of `dim`, `None` when the
+    /// system has no mapping for the dimension.
+    pub fn factor(self, dim: Dim, codata: &Codata) -> Option<f64> {
+        let c = SPEED_OF_LIGHT;
+        let p = |x: f64, k: i32| x.powi(k);
+        match self {
+            UnitSystem::Si => Some(1.0),
+            UnitSystem::Natural => {
+                if dim.curr != 0 || dim.lum != 0 || dim.amount != 0 || dim.currency != 0 {
+                    return None;
+                }
+                let ev = codata.e;
+                Some(
+                    p(ev / (codata.hbar * c), dim.len)
+                        * p(ev / codata.hbar, dim.time)
+                        * p(c * c / ev, dim.mass)
+                        * p(codata.k_b / ev, dim.temp),
+                )
+            }
+            UnitSystem::Planck => {
+                if dim.curr != 0 || dim.lum != 0 || dim.amount != 0 || dim.currency != 0 {
+                    return None;
+                }
+                let l_p = (codata.hbar * codata.g / c.powi(3)).sqrt();
+                let m_p = (codata.hbar * c / codata.g).sqrt();
+                let t_p = l_p / c;
+                let temp_p = m_p * c * c / codata.k_b;
+                Some(
+                    p(l_p, -dim.len) * p(m_p, -dim.mass) * p(t_p, -dim.time) * p(temp_p, -dim.temp),
+                )
+            }
+            UnitSystem::Gaussian => {
+                if MAGNETIC.contains(&dim) {
+                    return None;
+                }
+                Some(p(1e2, dim.len) * p(1e3, dim.mass) * p(10.0 * SPEED_OF_LIGHT, dim.curr))
+            }
+        }
+    }
+
+    /// Symbol of the unit a value of `dim` is expressed in by `factor`.
+    pub fn symbol(self, dim: Dim) -> String {
+        let currency = currency::base_symbol();
+        match self {
+            UnitSystem::Si => join_symbols(&[
+                ("kg", dim.mass),
+                ("m", dim.len),
+                ("s", dim.time),
+                ("K", dim.temp),
+                ("A", dim.curr),
+                ("cd", dim.lum),
+                ("mol", dim.amount),
+                (&currency, dim.currency),
+            ]),
+            UnitSystem::Natural => join_symbols(&[("eV", Self::energy_power(dim))]),
+            UnitSystem::Planck => join_symbols(&[
+                ("m_P", dim.mass),
+                ("l_P", dim.len),
+                ("t_P", dim.time),
+                ("T_P", dim.temp),
+            ]),
+            // A = C/s, so a current is a charge per time
+            UnitSystem::Gaussian => join_symbols(&[
+                ("g", dim.mass),
+                ("cm", dim.len),
+                ("statC", dim.curr),
+                ("s", dim.time - dim.curr),
+                ("K", dim.temp),
+                ("cd", dim.lum),
+                ("mol", dim.amount),
+                (&currency, dim.currency),
+            ]),
+        }
+    }
+
+    /// Whether a quantity of `from` can be converted to `to`. Natural and
+    /// Planck units identify dimensions that SI keeps apart.
+    pub fn bridges(self, from: Dim, to: Dim, codata: &Codata) -> bool {
+        if from == to {
+            return true;
+        }
+        let mapped = self.factor(from, codata).is_some() && self.factor(to, codata).is_some();
+        match self {
+            UnitSystem::Natural => mapped && Self::energy_power(from) == Self::energy_power(to),
+            UnitSystem::Planck => mapped,
+            UnitSystem::Si | UnitSystem::Gaussian => false,
+        }
+    }
+}
+
+impl Display for UnitSystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
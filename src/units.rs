@@ -1,18 +1,165 @@
 use std::fmt::{self, Display};
 
+use crate::constants::Codata;
+use crate::rational::Ratio;
+use crate::systems::UnitSystem;
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub struct Dim {
-    pub len: i64,
-    pub mass: i64,
-    pub time: i64,
-    pub temp: i64,
-    pub curr: i64,
+    pub len: i32,
+    pub mass: i32,
+    pub time: i32,
+    pub temp: i32,
+    pub curr: i32,
+    /// luminous intensity
+    pub lum: i32,
+    /// amount of substance
+    pub amount: i32,
+    /// money, measured in the base currency of the loaded exchange rates
+    pub currency: i32,
+}
+
+/// What a quantity is, beyond its dimension: torque and energy are both
+/// `L^2_M_T^-2`, but converting one into the other is usually a mistake.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum QuantityKind {
+    Length,
+    Time,
+    Force,
+    Energy,
+    Torque,
+    Frequency,
+    SolidAngle,
+    LuminousIntensity,
+    LuminousFlux,
+    Illuminance,
+    Luminance,
+    Activity,
+    AbsorbedDose,
+    EquivalentDose,
+    Exposure,
+}
+
+impl QuantityKind {
+    const ALL: [QuantityKind; 15] = [
+        QuantityKind::Length,
+        QuantityKind::Time,
+        QuantityKind::Force,
+        QuantityKind::Energy,
+        QuantityKind::Torque,
+        QuantityKind::Frequency,
+        QuantityKind::SolidAngle,
+        QuantityKind::LuminousIntensity,
+        QuantityKind::LuminousFlux,
+        QuantityKind::Illuminance,
+        QuantityKind::Luminance,
+        QuantityKind::Activity,
+        QuantityKind::AbsorbedDose,
+        QuantityKind::EquivalentDose,
+        QuantityKind::Exposure,
+    ];
+
+    pub fn from_name(name: &str) -> Option<QuantityKind> {
+        QuantityKind::ALL
+            .into_iter()
+            .find(|kind| kind.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            QuantityKind::Length => "length",
+            QuantityKind::Time => "time",
+            QuantityKind::Force => "force",
+            QuantityKind::Energy => "energy",
+            QuantityKind::Torque => "torque",
+            QuantityKind::Frequency => "frequency",
+            QuantityKind::SolidAngle => "solid angle",
+            QuantityKind::LuminousIntensity => "luminous intensity",
+            QuantityKind::LuminousFlux => "luminous flux",
+            QuantityKind::Illuminance => "illuminance",
+            QuantityKind::Luminance => "luminance",
+            QuantityKind::Activity => "activity",
+            QuantityKind::AbsorbedDose => "absorbed dose",
+            QuantityKind::EquivalentDose => "equivalent dose",
+            QuantityKind::Exposure => "exposure",
+        }
+    }
+
+    /// Kinds where a mix-up is dangerous, e.g. Gy vs Sv. Converting them to
+    /// another kind is rejected even outside strict mode.
+    pub fn is_guarded(self) -> bool {
+        matches!(
+            self,
+            QuantityKind::Activity | QuantityKind::AbsorbedDose | QuantityKind::EquivalentDose
+        )
+    }
+
+    /// Kinds that a per-time or per-mass form must keep, so that `mGy/hour`
+    /// and `mSv/hour` or `Bq/kg` and `Hz/kg` stay apart.
+    fn is_carried(self) -> bool {
+        self.is_guarded() || self == QuantityKind::Frequency
+    }
+
+    /// Kind of a product of two quantities, when it is a known one.
+    pub fn mul(a: Option<QuantityKind>, b: Option<QuantityKind>) -> Option<QuantityKind> {
+        use QuantityKind::*;
+        let carried = |kind: Option<QuantityKind>| kind.is_some_and(QuantityKind::is_carried);
+        match (a, b) {
+            (Some(Force), Some(Length)) | (Some(Length), Some(Force)) => Some(Torque),
+            (Some(LuminousIntensity), Some(SolidAngle))
+            | (Some(SolidAngle), Some(LuminousIntensity)) => Some(LuminousFlux),
+            (a, b) if carried(a) && !carried(b) => a,
+            (a, b) if carried(b) && !carried(a) => b,
+            _ => None,
+        }
+    }
+
+    /// Kind of a quantity raised to `k`. `s^-1` stays generic, it is the
+    /// unit of both frequency and activity.
+    pub fn pow(a: Option<QuantityKind>, k: i32) -> Option<QuantityKind> {
+        match (a?, k) {
+            (kind, 1) => Some(kind),
+            _ => None,
+        }
+    }
 }
 
+impl Display for QuantityKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Clone, Copy)]
 pub struct UnitDef {
     pub dim: Dim,
     pub factor: f64,
+    pub scale: Option<fn(&Codata) -> f64>,
+    pub system: Option<UnitSystem>,
+    pub kind: Option<QuantityKind>,
+    /// SI value of the unit's zero, non-zero only for temperature scales
+    pub offset: f64,
+    /// `factor` and `offset` as fractions, when the definition only uses
+    /// exact numbers: no `pi` and no CODATA constant
+    pub exact: Option<Exact>,
+}
+
+/// A factor and zero known exactly, such as 5/9 and 45967/180 for `degF`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Exact {
+    pub factor: Ratio,
+    pub offset: Ratio,
+}
+
+impl UnitDef {
+    pub fn si_factor(&self, codata: &Codata) -> f64 {
+        match self.scale {
+            Some(scale) => self.factor * scale(codata),
+            None => self.factor,
+        }
+    }
 }
+
 impl Display for Dim {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         // (symbol, exponent)
@@ -22,6 +169,9 @@ impl Display for Dim {
             ("T", self.time),
             ("Θ", self.temp),
             ("I", self.curr),
+            ("J", self.lum),
+            ("N", self.amount),
+            ("¤", self.currency),
         ];
 
         let mut first = true;
@@ -57,57 +207,84 @@ impl Dim {
         Dim::new(0, 0, 0, 0, 0)
     }
 
-    pub const fn new(len: i64, mass: i64, time: i64, temp: i64, curr: i64) -> Dim {
+    pub const fn new(len: i32, mass: i32, time: i32, temp: i32, curr: i32) -> Dim {
         Dim {
             len,
             mass,
             time,
             temp,
             curr,
+            lum: 0,
+            amount: 0,
+            currency: 0,
         }
     }
 
-    pub const fn len(p: i64) -> Dim {
+    pub const fn len(p: i32) -> Dim {
         Dim {
             len: p,
             ..Dim::zero()
         }
     }
 
-    pub const fn mass(p: i64) -> Dim {
+    pub const fn mass(p: i32) -> Dim {
         Dim {
             mass: p,
             ..Dim::zero()
         }
     }
 
-    pub const fn time(p: i64) -> Dim {
+    pub const fn time(p: i32) -> Dim {
         Dim {
             time: p,
             ..Dim::zero()
         }
     }
 
-    pub const fn temp(p: i64) -> Dim {
+    pub const fn temp(p: i32) -> Dim {
         Dim {
             temp: p,
             ..Dim::zero()
         }
     }
 
-    pub const fn curr(p: i64) -> Dim {
+    pub const fn curr(p: i32) -> Dim {
         Dim {
             curr: p,
             ..Dim::zero()
         }
     }
 
+    pub const fn lum(p: i32) -> Dim {
+        Dim {
+            lum: p,
+            ..Dim::zero()
+        }
+    }
+
+    pub const fn amount(p: i32) -> Dim {
+        Dim {
+            amount: p,
+            ..Dim::zero()
+        }
+    }
+
+    pub const fn currency(p: i32) -> Dim {
+        Dim {
+            currency: p,
+            ..Dim::zero()
+        }
+    }
+
     pub const fn mul(&mut self, other: Dim) {
         self.len += other.len;
         self.mass += other.mass;
         self.time += other.time;
         self.temp += other.temp;
         self.curr += other.curr;
+        self.lum += other.lum;
+        self.amount += other.amount;
+        self.currency += other.currency;
     }
 
     pub const fn div(&mut self, other: Dim) {
@@ -116,12 +293,18 @@ impl Dim {
         self.time -= other.time;
         self.temp -= other.temp;
         self.curr -= other.curr;
+        self.lum -= other.lum;
+        self.amount -= other.amount;
+        self.currency -= other.currency;
     }
-    pub const fn pow(&mut self, k: i64) {
+    pub const fn pow(&mut self, k: i32) {
         self.len *= k;
         self.mass *= k;
         self.time *= k;
         self.temp *= k;
         self.curr *= k;
+        self.lum *= k;
+        self.amount *= k;
+        self.currency *= k;
     }
 }